@@ -0,0 +1,596 @@
+use crate::CPU_FREQ;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// How many ticks of the frame counter a length counter value stands for
+#[rustfmt::skip]
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// The waveforms of the 4 duty cycles of the pulse channels: 12.5%, 25%, 50% and 75%
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+#[rustfmt::skip]
+const TRIANGLE_TABLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+/// The periods of the noise channel in cpu cycles (NTSC)
+const NOISE_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+/// At most this many seconds of samples are kept when nobody takes them
+const MAX_BUFFERED_SECONDS: usize = 1;
+
+/// Samples are handed to the sink in batches of this size, so it doesn't have to be locked
+/// for every sample
+const SINK_BATCH: usize = 256;
+
+/// Where [`Apu`] sends its samples when the window plays them
+pub(crate) type AudioSink = Arc<Mutex<VecDeque<f32>>>;
+
+/// Counts down the length of a note, shared by all channels
+#[derive(Default)]
+struct LengthCounter {
+    enabled: bool,
+    halted: bool,
+    value: u8,
+}
+
+impl LengthCounter {
+    fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.value = LENGTH_TABLE[index as usize];
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.value = 0;
+        }
+    }
+
+    fn clock(&mut self) {
+        if !self.halted && self.value > 0 {
+            self.value -= 1;
+        }
+    }
+}
+
+/// The volume of the pulse and noise channels, fading out or constant
+#[derive(Default)]
+struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    /// The constant volume, or the period of the fade
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.looping = value & 0b0010_0000 != 0;
+        self.constant = value & 0b0001_0000 != 0;
+        self.volume = value & 0b0000_1111;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pulse {
+    /// The first pulse channel negates its sweep slightly differently from the second
+    first: bool,
+    duty: u8,
+    step: u8,
+    period: u16,
+    timer: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_reload: bool,
+    sweep_divider: u8,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.length.halted = value & 0b0010_0000 != 0;
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep_enabled = value & 0b1000_0000 != 0;
+                self.sweep_period = (value >> 4) & 0b111;
+                self.sweep_negate = value & 0b0000_1000 != 0;
+                self.sweep_shift = value & 0b111;
+                self.sweep_reload = true;
+            }
+            2 => self.period = (self.period & 0x0700) | u16::from(value),
+            _ => {
+                self.period = (self.period & 0x00ff) | (u16::from(value & 0b111) << 8);
+                self.length.load(value >> 3);
+                self.step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    /// The period the sweep unit wants to change to
+    fn sweep_target(&self) -> u16 {
+        let change = self.period >> self.sweep_shift;
+        if self.sweep_negate {
+            // the first channel subtracts one more (ones' complement)
+            self.period
+                .saturating_sub(change)
+                .saturating_sub(u16::from(self.first))
+        } else {
+            self.period + change
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x7ff
+    }
+
+    /// Called every other cpu cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length.value == 0 || self.muted() {
+            return 0;
+        }
+        DUTY_TABLE[self.duty as usize][self.step as usize] * self.envelope.output()
+    }
+}
+
+#[derive(Default)]
+struct Triangle {
+    step: u8,
+    period: u16,
+    timer: u16,
+    length: LengthCounter,
+
+    /// Also halts the length counter
+    linear_control: bool,
+    linear_reload_value: u8,
+    linear_reload: bool,
+    linear: u8,
+}
+
+impl Triangle {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.linear_control = value & 0b1000_0000 != 0;
+                self.length.halted = self.linear_control;
+                self.linear_reload_value = value & 0b0111_1111;
+            }
+            1 => {}
+            2 => self.period = (self.period & 0x0700) | u16::from(value),
+            _ => {
+                self.period = (self.period & 0x00ff) | (u16::from(value & 0b111) << 8);
+                self.length.load(value >> 3);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    /// Called every cpu cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length.value > 0 && self.linear > 0 {
+                self.step = (self.step + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear = self.linear_reload_value;
+        } else if self.linear > 0 {
+            self.linear -= 1;
+        }
+        if !self.linear_control {
+            self.linear_reload = false;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        // very short periods are above what anyone can hear, and only cause popping
+        if self.period < 2 {
+            return 7;
+        }
+        TRIANGLE_TABLE[self.step as usize]
+    }
+}
+
+struct Noise {
+    /// Makes a short, metallic sounding loop instead of noise
+    short_mode: bool,
+    period: u16,
+    timer: u16,
+    shift: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            short_mode: false,
+            period: NOISE_TABLE[0],
+            timer: 0,
+            shift: 1,
+            length: LengthCounter::default(),
+            envelope: Envelope::default(),
+        }
+    }
+}
+
+impl Noise {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.length.halted = value & 0b0010_0000 != 0;
+                self.envelope.write(value);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = value & 0b1000_0000 != 0;
+                self.period = NOISE_TABLE[(value & 0b1111) as usize];
+            }
+            _ => {
+                self.length.load(value >> 3);
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    /// Called every cpu cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period - 1;
+            let other = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> other)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length.value == 0 || self.shift & 1 != 0 {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
+
+/// Emulates the sound chip of the NES (the APU): its two pulse channels, the triangle
+/// channel and the noise channel. The DMC channel, which plays samples from cpu memory, is
+/// not emulated.
+///
+/// Your cpu writes to its registers at `0x4000..=0x4013`, `0x4015` and `0x4017`, which
+/// [`Ppu::write_cpu_bus`](crate::Ppu::write_cpu_bus) forwards here. It is clocked once
+/// every cpu cycle by the run functions of this crate. With the `audio` feature,
+/// [`run_cpu`](crate::run_cpu) plays the sound.
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+
+    /// Cpu cycles since the start of the frame counter sequence
+    frame_cycle: u32,
+    five_step_mode: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    /// Pulse timers are clocked every other cpu cycle
+    odd_cycle: bool,
+
+    /// Samples per second, or `None` when no samples are made
+    sample_rate: Option<u32>,
+    /// Cpu cycles per second, see [`RunConfig::cpu_freq`](crate::RunConfig::cpu_freq)
+    pub(crate) cpu_freq: f64,
+    /// Counts up by the sample rate every cpu cycle, a sample is made when it passes the cpu
+    /// frequency
+    sample_clock: f64,
+    /// The sum of the outputs since the last sample, which are averaged into the next one
+    sample_sum: f32,
+    sample_count: u32,
+    /// The last input and output of the filter that removes the DC offset
+    filter: (f32, f32),
+    samples: VecDeque<f32>,
+    pub(crate) sink: Option<AudioSink>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self {
+            pulse1: Pulse {
+                first: true,
+                ..Pulse::default()
+            },
+            pulse2: Pulse::default(),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            frame_cycle: 0,
+            five_step_mode: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            odd_cycle: false,
+            sample_rate: None,
+            cpu_freq: CPU_FREQ,
+            sample_clock: 0.0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            filter: (0.0, 0.0),
+            samples: VecDeque::new(),
+            sink: None,
+        }
+    }
+}
+
+impl Apu {
+    /// Resets the sound chip to its power-on state, but keeps where the samples go
+    pub(crate) fn reset(&mut self) {
+        let fresh = Self {
+            sample_rate: self.sample_rate,
+            cpu_freq: self.cpu_freq,
+            sink: self.sink.take(),
+            ..Self::default()
+        };
+        *self = fresh;
+    }
+
+    /// Throws away the samples that are waiting to be played, so the sound stops right away
+    pub(crate) fn mute(&mut self) {
+        self.samples.clear();
+        if let Some(sink) = &self.sink {
+            sink.lock().expect("failed to lock").clear();
+        }
+    }
+
+    /// Handles a cpu write to one of the APU registers: `0x4000..=0x4013`, `0x4015` or
+    /// `0x4017`. Returns whether `address` was one of them. Writes to the DMC channel
+    /// (`0x4010..=0x4013`) are accepted but do nothing.
+    pub fn write_register(&mut self, address: u16, value: u8) -> bool {
+        match address {
+            0x4000..=0x4003 => self.pulse1.write(address - 0x4000, value),
+            0x4004..=0x4007 => self.pulse2.write(address - 0x4004, value),
+            0x4008..=0x400b => self.triangle.write(address - 0x4008, value),
+            0x400c..=0x400f => self.noise.write(address - 0x400c, value),
+            0x4010..=0x4013 => {}
+            0x4015 => {
+                self.pulse1.length.set_enabled(value & 0b0001 != 0);
+                self.pulse2.length.set_enabled(value & 0b0010 != 0);
+                self.triangle.length.set_enabled(value & 0b0100 != 0);
+                self.noise.length.set_enabled(value & 0b1000 != 0);
+            }
+            0x4017 => {
+                self.five_step_mode = value & 0b1000_0000 != 0;
+                self.irq_inhibit = value & 0b0100_0000 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                if self.five_step_mode {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// A cpu read from `0x4015`. Bits 0 to 3 say whether the pulse, triangle and noise
+    /// channels are still playing a note, and bit 6 whether the frame counter asked for an
+    /// interrupt. Reading clears that interrupt flag.
+    pub fn read_status(&mut self) -> u8 {
+        let playing = [
+            &self.pulse1.length,
+            &self.pulse2.length,
+            &self.triangle.length,
+            &self.noise.length,
+        ]
+        .iter()
+        .enumerate()
+        .filter(|(_, length)| length.value > 0)
+        .fold(0, |status, (idx, _)| status | 1 << idx);
+        let irq = if self.frame_irq { 0b0100_0000 } else { 0 };
+
+        self.frame_irq = false;
+        playing | irq
+    }
+
+    /// Starts making samples at `sample_rate` samples per second, to be taken with
+    /// [`Apu::take_samples`]. `None` (the default) stops making them.
+    pub fn set_sample_rate(&mut self, sample_rate: Option<u32>) {
+        self.sample_rate = sample_rate;
+        self.samples.clear();
+    }
+
+    /// Takes the samples made since the last call, from -1.0 to 1.0. See
+    /// [`Apu::set_sample_rate`]. When they are not taken, at most a second of them is kept.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples).into()
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.length.clock();
+        self.pulse2.length.clock();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+        match (self.frame_cycle, self.five_step_mode) {
+            (7457, _) | (22371, _) => self.clock_quarter_frame(),
+            (14913, _) | (29829, false) | (37281, true) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            _ => {}
+        }
+
+        if self.frame_cycle == 29829 && !self.five_step_mode && !self.irq_inhibit {
+            self.frame_irq = true;
+        }
+        if self.frame_cycle >= if self.five_step_mode { 37282 } else { 29830 } {
+            self.frame_cycle = 0;
+        }
+    }
+
+    /// The mixed output of all channels, from 0.0 to 1.0, the way the real NES mixes them
+    fn mix(&self) -> f32 {
+        let pulse = f32::from(self.pulse1.output() + self.pulse2.output());
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let triangle = f32::from(self.triangle.output());
+        let noise = f32::from(self.noise.output());
+        let tnd = triangle / 8227.0 + noise / 12241.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse_out + tnd_out
+    }
+
+    /// Runs the APU for one cpu cycle
+    pub(crate) fn tick(&mut self) {
+        self.clock_frame_counter();
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        let Some(sample_rate) = self.sample_rate else {
+            return;
+        };
+
+        self.sample_sum += self.mix();
+        self.sample_count += 1;
+        self.sample_clock += f64::from(sample_rate);
+        if self.sample_clock >= self.cpu_freq {
+            self.sample_clock -= self.cpu_freq;
+            self.push_sample(sample_rate);
+        }
+    }
+
+    fn push_sample(&mut self, sample_rate: u32) {
+        let input = self.sample_sum / self.sample_count as f32;
+        self.sample_sum = 0.0;
+        self.sample_count = 0;
+
+        // a high pass filter at about 40 Hz, so the sound is centered around 0
+        let dt = 1.0 / sample_rate as f32;
+        let rc = 1.0 / (2.0 * std::f32::consts::PI * 40.0);
+        let (last_input, last_output) = self.filter;
+        let output = rc / (rc + dt) * (last_output + input - last_input);
+        self.filter = (input, output);
+
+        self.samples.push_back(output.clamp(-1.0, 1.0));
+
+        if let Some(sink) = &self.sink {
+            if self.samples.len() >= SINK_BATCH {
+                let mut sink = sink.lock().expect("failed to lock");
+                sink.extend(self.samples.drain(..));
+
+                // when the sound can't keep up, drop the oldest samples instead of lagging
+                // further and further behind
+                let max = sample_rate as usize / 10;
+                if sink.len() > max {
+                    let excess = sink.len() - max;
+                    sink.drain(..excess);
+                }
+            }
+        } else {
+            if self.samples.len() > sample_rate as usize * MAX_BUFFERED_SECONDS {
+                self.samples.pop_front();
+            }
+        }
+    }
+}
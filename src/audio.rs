@@ -0,0 +1,88 @@
+use crate::apu::AudioSink;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Plays the samples of the [`Apu`](crate::Apu) on the default output device. The stream stops
+/// when this is dropped.
+pub(crate) struct Audio {
+    _stream: Stream,
+    sample_rate: u32,
+    samples: AudioSink,
+}
+
+impl Audio {
+    /// `None` when there is no output device, or it can't be opened.
+    pub(crate) fn new() -> Option<Self> {
+        let device = cpal::default_host().default_output_device()?;
+        let supported = match device.default_output_config() {
+            Ok(supported) => supported,
+            Err(e) => {
+                log::warn!("sound is not available: {e}");
+                return None;
+            }
+        };
+
+        let samples = Arc::new(Mutex::new(VecDeque::new()));
+        let config = supported.config();
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => Self::stream::<f32>(&device, &config, samples.clone()),
+            SampleFormat::I16 => Self::stream::<i16>(&device, &config, samples.clone()),
+            SampleFormat::U16 => Self::stream::<u16>(&device, &config, samples.clone()),
+            format => {
+                log::warn!("sound is not available: unsupported sample format {format}");
+                return None;
+            }
+        };
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("sound is not available: {e}");
+                return None;
+            }
+        };
+        if let Err(e) = stream.play() {
+            log::warn!("sound is not available: {e}");
+            return None;
+        }
+
+        Some(Self {
+            _stream: stream,
+            sample_rate: config.sample_rate.0,
+            samples,
+        })
+    }
+
+    fn stream<T: SizedSample + FromSample<f32>>(
+        device: &cpal::Device,
+        config: &StreamConfig,
+        samples: AudioSink,
+    ) -> Result<Stream, Box<dyn std::error::Error>> {
+        let channels = config.channels as usize;
+        let stream = device.build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                let mut samples = samples.lock().expect("failed to lock");
+                for frame in data.chunks_mut(channels) {
+                    // play silence when the emulation can't keep up
+                    let sample = T::from_sample(samples.pop_front().unwrap_or(0.0));
+                    frame.fill(sample);
+                }
+            },
+            |e| log::warn!("error while playing sound: {e}"),
+            None,
+        )?;
+        Ok(stream)
+    }
+
+    /// The samples per second the device plays
+    pub(crate) fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Where the [`Apu`](crate::Apu) should put its samples
+    pub(crate) fn sink(&self) -> AudioSink {
+        self.samples.clone()
+    }
+}
@@ -0,0 +1,220 @@
+#[cfg(feature = "display")]
+use crate::{Icon, KeyMap};
+use crate::{ButtonName, EmulationStats, Resolution, CPU_FREQ};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// How the emulation waits to stay in sync with real time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Pacing {
+    /// Sleep with [`std::thread::sleep`]. Uses little cpu, but on platforms where sleeping
+    /// is imprecise (notably Windows) the emulation may stutter.
+    #[default]
+    Sleep,
+    /// Sleep most of the time, and busy-wait for the last millisecond. Uses more cpu but
+    /// is much more precise.
+    SpinHybrid,
+}
+
+/// The mouse button that pulls the trigger of the light gun (zapper).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TriggerButton {
+    #[default]
+    Left,
+    Right,
+    Middle,
+}
+
+/// How finished frames are shown in the window, see [`RunConfig::present_mode`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PresentMode {
+    /// Wait for the monitor to refresh, so there is no tearing. Uses [`PresentMode::Fifo`]
+    /// or another mode with vsync that the platform supports.
+    #[default]
+    AutoVsync,
+    /// Show frames right away for the lowest latency, even if that causes tearing. Uses
+    /// [`PresentMode::Immediate`] or [`PresentMode::Mailbox`] when the platform supports them,
+    /// and falls back to [`PresentMode::Fifo`].
+    AutoNoVsync,
+    /// Wait for the monitor to refresh, supported everywhere
+    Fifo,
+    /// Show frames right away, which can cause tearing. Not supported everywhere.
+    Immediate,
+    /// Show the newest frame at the next refresh of the monitor, dropping older ones. Low
+    /// latency without tearing, but not supported everywhere.
+    Mailbox,
+}
+
+/// What one player plays with, see [`RunConfig::players`].
+#[cfg(feature = "display")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlayerInput {
+    /// Keys on the keyboard. The buttons in the map are pressed on this player's controller,
+    /// no matter which controller their names are for. So
+    /// [`KeyMap::for_controller`]`(0)` can be used for either player.
+    Keyboard(KeyMap),
+    /// A gamepad: 0 is the first one that was connected, 1 the second. Needs the `gamepad`
+    /// feature.
+    Gamepad(usize),
+    /// Nothing, the controller is never pressed
+    None,
+}
+
+/// Settings for running the emulator with [`run_cpu_with_config`](crate::run_cpu_with_config).
+///
+/// Use [`RunConfig::default`] for the settings [`run_cpu`](crate::run_cpu) uses, and change
+/// only what you need:
+///
+/// ```
+/// # use std::time::Duration;
+/// # use tudelft_nes_ppu::RunConfig;
+/// let config = RunConfig {
+///     behind_threshold: Duration::from_millis(500),
+///     ..RunConfig::default()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct RunConfig {
+    /// When the emulation runs behind real time by more than this, a warning is logged
+    /// (through the `log` crate). Defaults to 200 ms.
+    pub behind_threshold: Duration,
+    /// When set, [`EmulationStats`] are sent over this channel about once every frame
+    /// (roughly 60 times per second), for example to show the emulation speed on screen.
+    pub stats: Option<Sender<EmulationStats>>,
+    /// Two left clicks within this time of each other (and at most a few pixels apart) are
+    /// a double click, see [`Ppu::take_double_click`](crate::Ppu::take_double_click).
+    /// Defaults to 400 ms.
+    pub double_click_interval: Duration,
+    /// How fast the emulation runs compared to a real NES: 0.25 is slow motion at a quarter of
+    /// the speed, 2.0 is twice as fast. [`f64::INFINITY`] runs as fast as possible. Holding
+    /// tab in the window fast-forwards at that speed. Has to be more than 0. Defaults to 1.0.
+    pub speed: f64,
+    /// How many cpu cycles per second a real NES runs, which is what [`RunConfig::speed`] is
+    /// relative to. Defaults to [`CPU_FREQ`], the clock of an NTSC NES. A PAL NES runs at
+    /// about 1.662 MHz, but note that the PPU still draws NTSC frames of 262 lines. Has to be
+    /// more than 0.
+    pub cpu_freq: f64,
+    /// How many cpu cycles run between two waits for real time to catch up. Input from the
+    /// window is checked before every cycle, but input that arrives while the emulation
+    /// waits is only seen in the next batch. A batch of 1000 cycles is about 0.56 ms of NES
+    /// time. Smaller batches pick up input sooner and keep closer to real time, but wait
+    /// more often, which costs more cpu time. Has to be at least 1. Defaults to 1000.
+    pub batch_cycles: usize,
+    /// How to wait for real time to catch up with the emulation. Defaults to [`Pacing::Sleep`].
+    pub pacing: Pacing,
+    /// The title of the window. It can be changed while running with
+    /// [`Ppu::set_window_title`](crate::Ppu::set_window_title). Defaults to `"NES"`.
+    pub title: String,
+    /// How many times larger than [`RunConfig::resolution`] the window starts. When that
+    /// doesn't fit on the monitor, the largest scale that does is used instead. Has to be at
+    /// least 1. Defaults to 2.
+    pub scale: u32,
+    /// The size of the frame, see [`Resolution`] for what the space around the NES screen
+    /// can be used for. Defaults to [`Resolution::NES`].
+    pub resolution: Resolution,
+    /// Whether the window can be resized. Defaults to `true`.
+    pub resizable: bool,
+    /// The smallest size (width, height) in pixels the window can be resized to, if any.
+    /// Defaults to `None`.
+    pub min_size: Option<(u32, u32)>,
+    /// When the window is resized, scale the frame by the largest whole number that fits and
+    /// show black bars around it, so it keeps the right aspect ratio and pixels stay sharp.
+    /// When `false`, the frame is stretched to fill the whole window. Defaults to `false`.
+    pub integer_scaling: bool,
+    /// The mouse button that is the light gun trigger
+    /// ([`ButtonName::MouseLeft`](crate::ButtonName::MouseLeft)). Other mouse buttons don't
+    /// touch the trigger. Double clicks are always done with the left button.
+    /// Defaults to [`TriggerButton::Left`].
+    pub trigger_button: TriggerButton,
+    /// How frames are shown in the window. Vsync prevents tearing, but on some setups it adds
+    /// latency to the input. When the platform doesn't support the chosen mode, the window
+    /// can't be created and the run function panics. The `Auto` modes always work. Defaults
+    /// to [`PresentMode::AutoVsync`].
+    pub present_mode: PresentMode,
+    /// Buttons that fire automatically while they're held: they are pressed and released again
+    /// and again, this many times per second. For example `(ButtonName::A1, 15.0)` for a
+    /// rapid fire A button. Turbo buttons switch at the start of a frame, so the cpu always
+    /// sees the same pattern. The speed is rounded to a whole number of frames, and is at
+    /// most 30 times per second. Defaults to none.
+    pub turbo: Vec<(ButtonName, f64)>,
+    /// Only let button presses and releases take effect at the start of a frame (the start of
+    /// vblank), instead of in the middle of the cycle they happened at. The cpu then sees the
+    /// exact same input when the same button states are replayed per frame, see
+    /// [`run_cpu_recording`](crate::run_cpu_recording). Defaults to `false`.
+    pub buttons_per_frame: bool,
+    /// How many seconds back the emulation can be rewound by holding backspace in the window.
+    /// Every frame costs a snapshot of the PPU (about 4.5KB) and one of the cpu, see
+    /// [`Cpu::snapshot`](crate::Cpu::snapshot). Rewinding only works when the cpu implements
+    /// that. 0 disables rewinding. Defaults to 10.
+    pub rewind_seconds: u32,
+    /// Whether the sound stops while the emulation is paused, because the window lost focus or
+    /// the cpu called [`Ppu::pause`](crate::Ppu::pause). When `false`, the sound chip keeps
+    /// running while paused, so the notes that were playing go on until they end by themselves.
+    /// Only makes a difference with the `audio` feature. Defaults to `true`.
+    pub mute_on_pause: bool,
+    /// Which keys press which buttons. Defaults to [`KeyMap::default`]. Not used when
+    /// [`RunConfig::players`] is set.
+    #[cfg(feature = "display")]
+    pub keymap: KeyMap,
+    /// What controller 1 and controller 2 are played with, for example so both players have
+    /// their own gamepad:
+    ///
+    /// ```
+    /// # use tudelft_nes_ppu::{PlayerInput, RunConfig};
+    /// let config = RunConfig {
+    ///     players: Some([PlayerInput::Gamepad(0), PlayerInput::Gamepad(1)]),
+    ///     ..RunConfig::default()
+    /// };
+    /// ```
+    ///
+    /// Defaults to `None`: the keys of [`RunConfig::keymap`] press the buttons of both
+    /// controllers, and with the `gamepad` feature the first two gamepads play controller 1
+    /// and 2.
+    #[cfg(feature = "display")]
+    pub players: Option<[PlayerInput; 2]>,
+    /// Sets the `WAYLAND_DISPLAY` environment variable to this before the window is created,
+    /// for systems where the window can't find the Wayland compositor by itself. Note that
+    /// this changes the environment of the whole process. Defaults to `None`, which leaves
+    /// it to the environment whether Wayland or X11 is used.
+    #[cfg(feature = "display")]
+    pub wayland_display: Option<String>,
+    /// The icon of the window. Create one from RGBA bytes with [`Icon::from_rgba`], which
+    /// checks that there are exactly `width * height * 4` of them. Defaults to `None`, the
+    /// icon of the platform.
+    #[cfg(feature = "display")]
+    pub icon: Option<Icon>,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            behind_threshold: Duration::from_millis(200),
+            stats: None,
+            double_click_interval: Duration::from_millis(400),
+            speed: 1.0,
+            cpu_freq: CPU_FREQ,
+            batch_cycles: 1000,
+            pacing: Pacing::default(),
+            title: "NES".to_string(),
+            scale: 2,
+            resolution: Resolution::NES,
+            resizable: true,
+            min_size: None,
+            integer_scaling: false,
+            trigger_button: TriggerButton::default(),
+            present_mode: PresentMode::default(),
+            turbo: Vec::new(),
+            buttons_per_frame: false,
+            rewind_seconds: 10,
+            mute_on_pause: true,
+            #[cfg(feature = "display")]
+            keymap: KeyMap::default(),
+            #[cfg(feature = "display")]
+            players: None,
+            #[cfg(feature = "display")]
+            wayland_display: None,
+            #[cfg(feature = "display")]
+            icon: None,
+        }
+    }
+}
@@ -6,10 +6,19 @@ use std::error::Error;
 pub trait Cpu {
     type TickError: Error;
 
-    /// Called every cpu cycle. Note that some instructions take multiple cycles, which
-    /// is important for some games to work properly. That means that it *won't* work to
-    /// execute an entire instruction every time tick is called. It should take *multiple*
-    /// calls to tick to execute one instruction.
+    /// How many PPU cycles (dots) pass for every call to [`Cpu::tick`]. On a real NTSC NES
+    /// the PPU runs exactly 3 times as fast as the cpu, which is the default. If you model
+    /// your cpu at a different granularity (for example 6 for two cpu cycles per tick), you
+    /// can change this ratio accordingly. The emulation still runs at the speed of a real
+    /// NES, and so does the sound. Has to be more than 0.
+    const DOTS_PER_TICK: usize = 3;
+
+    /// Called every [`Cpu::DOTS_PER_TICK`] dots, which is every cpu cycle by default. Note
+    /// that some instructions take multiple cycles, which is important for some games to work
+    /// properly. With the default, executing an entire instruction every time tick is called
+    /// *won't* work: it should take *multiple* calls to tick to execute one instruction.
+    /// With a larger [`Cpu::DOTS_PER_TICK`], each tick should do that much more work, but
+    /// games that depend on exact timing may break.
     fn tick(&mut self, ppu: &mut Ppu) -> Result<(), Self::TickError>;
 
     /// This method is called when the PPU (implemented by us) wants to read a byte from memory.
@@ -24,5 +33,45 @@ pub trait Cpu {
 
     /// Sometimes the PPU needs to give a non-maskable interrupt to the cpu. If it does, this method
     /// is called by the PPU.
+    ///
+    /// This happens at the start of vblank (scanline 241), and only when NMIs are enabled in
+    /// the controller register (`0x2000`). It's called between two calls to [`Cpu::tick`]: the
+    /// PPU runs its dots right after a tick returns, so the NMI arrives after the tick in which
+    /// the PPU reached vblank, and before the next one. Like on real hardware, the cpu should
+    /// finish the instruction it's executing before jumping to the NMI handler. If you'd rather
+    /// poll for it, see [`Ppu::take_nmi`].
     fn non_maskable_interrupt(&mut self);
+
+    /// Called once when the window of [`run_cpu`](crate::run_cpu) is closed, right before the
+    /// program exits. Use this to clean up, like writing a save file to disk. The emulation
+    /// has stopped by then, [`Cpu::tick`] won't be called again. The default implementation
+    /// does nothing.
+    fn on_exit(&mut self) {}
+
+    /// Takes a snapshot of the whole state of the cpu (registers, ram, mapper state) to
+    /// restore later with [`Cpu::restore`], in any format you like. [`run_cpu`](crate::run_cpu)
+    /// takes one every frame to rewind while backspace is held, see
+    /// [`RunConfig::rewind_seconds`](crate::RunConfig::rewind_seconds). The state of the PPU
+    /// is saved along with it, see [`Ppu::save_state`].
+    ///
+    /// The default implementation returns `None`, which means the cpu can't be rewound, so
+    /// rewinding is disabled.
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Goes back to a snapshot made by [`Cpu::snapshot`]. The default implementation does
+    /// nothing.
+    fn restore(&mut self, _snapshot: &[u8]) {}
+
+    /// Only needed for [`run_test_rom`](crate::run_test_rom). Test roms report their result
+    /// through memory: as soon as the bytes `0xDE 0xB0 0x61` are present at `0x6001..=0x6003`,
+    /// the byte at `0x6000` is the status of the test. Return that status byte here, or `None`
+    /// while the signature isn't there (yet). The default implementation always returns `None`.
+    ///
+    /// A status of `0x80` means the test is still running, `0x81` means the rom wants
+    /// to be reset, `0x00` means that all tests passed and anything else is an error code.
+    fn test_status(&self) -> Option<u8> {
+        None
+    }
 }
@@ -0,0 +1,93 @@
+use crate::ButtonName;
+use gilrs::{Button, EventType, GamepadId, Gilrs};
+
+/// Turns gamepad input into button presses. The first gamepad that is connected is the first
+/// controller, the second gamepad the second controller. Gamepads can be (un)plugged at any
+/// time: a newly connected gamepad takes the first controller that is free.
+pub(crate) struct Gamepads {
+    gilrs: Gilrs,
+    /// The gamepad that is used as each controller
+    controllers: [Option<GamepadId>; 2],
+}
+
+impl Gamepads {
+    /// `None` when gamepads are not supported on this platform.
+    pub(crate) fn new() -> Option<Self> {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => gilrs,
+            Err(e) => {
+                log::warn!("gamepads are not available: {e}");
+                return None;
+            }
+        };
+
+        let mut gamepads = Self {
+            gilrs,
+            controllers: [None; 2],
+        };
+        let connected: Vec<_> = gamepads.gilrs.gamepads().map(|(id, _)| id).collect();
+        for id in connected {
+            gamepads.controller(id);
+        }
+
+        Some(gamepads)
+    }
+
+    /// The controller (0 or 1) gamepad `id` is, giving it a free one if it doesn't have one yet.
+    fn controller(&mut self, id: GamepadId) -> Option<usize> {
+        if let Some(controller) = self.controllers.iter().position(|&c| c == Some(id)) {
+            return Some(controller);
+        }
+
+        let free = self.controllers.iter().position(Option::is_none)?;
+        self.controllers[free] = Some(id);
+        Some(free)
+    }
+
+    /// Handles all gamepad events that happened since the last call, and calls `press` for
+    /// every button that was pressed (`true`) or released (`false`), with the gamepad (0 or
+    /// 1) it was pressed on.
+    pub(crate) fn poll(&mut self, mut press: impl FnMut(usize, ButtonName, bool)) {
+        while let Some(event) = self.gilrs.next_event() {
+            let (button, pressed) = match event.event {
+                EventType::ButtonPressed(button, _) => (button, true),
+                EventType::ButtonReleased(button, _) => (button, false),
+                EventType::Disconnected => {
+                    for controller in &mut self.controllers {
+                        if *controller == Some(event.id) {
+                            *controller = None;
+                        }
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let Some(controller) = self.controller(event.id) else {
+                continue;
+            };
+            if let Some(name) = button_name(button, controller) {
+                press(controller, name, pressed);
+            }
+        }
+    }
+}
+
+/// The button a gamepad button presses on `controller` (0 or 1).
+fn button_name(button: Button, controller: usize) -> Option<ButtonName> {
+    use ButtonName::*;
+
+    let names = match button {
+        Button::DPadUp => (Up1, Up2),
+        Button::DPadDown => (Down1, Down2),
+        Button::DPadLeft => (Left1, Left2),
+        Button::DPadRight => (Right1, Right2),
+        Button::South => (A1, A2),
+        Button::East => (B1, B2),
+        Button::Start => (Start1, Start2),
+        Button::Select => (Select1, Select2),
+        _ => return None,
+    };
+
+    Some(if controller == 0 { names.0 } else { names.1 })
+}
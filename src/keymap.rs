@@ -0,0 +1,96 @@
+use crate::ButtonName;
+use std::collections::HashMap;
+use winit::event::VirtualKeyCode;
+
+/// Which keyboard keys press which buttons in the window opened by [`run_cpu`](crate::run_cpu).
+/// Keys that aren't in the map are ignored. Every key presses at most one button, but a button
+/// can have multiple keys. To change the keys while the window is open, use
+/// [`Ppu::rebind_key`](crate::Ppu::rebind_key).
+///
+/// [`KeyMap::default`] has the bindings [`run_cpu`](crate::run_cpu) uses:
+///
+/// | Button       | Controller 1  | Controller 2                         |
+/// |--------------|---------------|--------------------------------------|
+/// | Up           | `W`           | `Up` or `I`                          |
+/// | Down         | `S`           | `Down` or `K`                        |
+/// | Left         | `A`           | `Left` or `J`                        |
+/// | Right        | `D`           | `Right` or `L`                       |
+/// | A            | `X` or `G`    | `Numpad2` or `Apostrophe`            |
+/// | B            | `Z` or `F`    | `Numpad1` or `Semicolon`             |
+/// | Start        | `Space`       | `Return`                             |
+/// | Select       | `LShift`      | `RShift`                             |
+///
+/// ```
+/// # use tudelft_nes_ppu::{ButtonName, KeyMap, VirtualKeyCode};
+/// let mut keymap = KeyMap::default();
+/// keymap.bind(VirtualKeyCode::K, ButtonName::A1);
+/// keymap.unbind(VirtualKeyCode::X);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMap(HashMap<VirtualKeyCode, ButtonName>);
+
+impl KeyMap {
+    /// A map without any keys bound.
+    pub fn empty() -> Self {
+        Self(HashMap::new())
+    }
+
+    /// The keys of [`KeyMap::default`] for one controller (0 or 1) only, to give each player
+    /// their own keys with [`PlayerInput::Keyboard`](crate::PlayerInput::Keyboard).
+    pub fn for_controller(controller: usize) -> Self {
+        let mut keymap = Self::default();
+        keymap
+            .0
+            .retain(|_, button| button.on_controller(controller) == *button);
+        keymap
+    }
+
+    /// Makes `key` press `button`, replacing what `key` was bound to before.
+    pub fn bind(&mut self, key: VirtualKeyCode, button: ButtonName) {
+        self.0.insert(key, button);
+    }
+
+    /// Makes `key` not press any button anymore.
+    pub fn unbind(&mut self, key: VirtualKeyCode) {
+        self.0.remove(&key);
+    }
+
+    /// The button `key` presses, if any.
+    pub fn get(&self, key: VirtualKeyCode) -> Option<ButtonName> {
+        self.0.get(&key).copied()
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        use ButtonName::*;
+        use VirtualKeyCode as Key;
+
+        Self(HashMap::from([
+            (Key::W, Up1),
+            (Key::S, Down1),
+            (Key::A, Left1),
+            (Key::D, Right1),
+            (Key::X, A1),
+            (Key::G, A1),
+            (Key::Z, B1),
+            (Key::F, B1),
+            (Key::Space, Start1),
+            (Key::LShift, Select1),
+            (Key::Up, Up2),
+            (Key::I, Up2),
+            (Key::Down, Down2),
+            (Key::K, Down2),
+            (Key::Left, Left2),
+            (Key::J, Left2),
+            (Key::Right, Right2),
+            (Key::L, Right2),
+            (Key::Numpad2, A2),
+            (Key::Apostrophe, A2),
+            (Key::Numpad1, B2),
+            (Key::Semicolon, B2),
+            (Key::Return, Start2),
+            (Key::RShift, Select2),
+        ]))
+    }
+}
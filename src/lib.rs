@@ -5,15 +5,56 @@ pub const HEIGHT: u32 = 240;
 
 /// CPU frequency constant. This assumes NTSC emulation (instead of PAL).
 /// That's also what's emulated in the rest of the ppu.
+/// The emulation runs at this frequency unless [`RunConfig::cpu_freq`] says otherwise.
 pub const CPU_FREQ: f64 = 1.789_773 * 1_000_000.0; //hz
 
+mod apu;
+#[cfg(feature = "audio")]
+mod audio;
+mod config;
 mod cpu;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+#[cfg(feature = "display")]
+mod keymap;
+mod peripheral;
 mod ppu;
+mod rom;
 mod run;
 mod screen;
+mod stepper;
+#[cfg(feature = "display")]
+mod stretch;
 
+pub use apu::Apu;
+pub use config::{Pacing, PresentMode, RunConfig, TriggerButton};
 pub use cpu::Cpu;
+pub use peripheral::Peripheral;
+pub use ppu::colors::{Color, ColorExt};
 pub use ppu::mirroring::Mirroring;
-pub use ppu::{registers::PpuRegister, Ppu};
-pub use run::{run_cpu, run_cpu_headless, run_cpu_headless_for};
-pub use screen::Buttons;
+pub use ppu::{
+    registers::PpuRegister, snapshot::PpuSnapshot, state::StateError, MemorySizeError, Ppu,
+};
+pub use rom::{Rom, RomError};
+pub use run::{
+    check_determinism, run_cpu_headless, run_cpu_headless_for, run_cpu_headless_replay,
+    run_cpu_headless_stats, run_cpu_headless_with_buttons, run_cpu_headless_with_input,
+    run_cpu_with_backend, run_test_rom, run_two_cpus, window_to_nes, EmulationStats, ReplayError,
+    RunStats, TestResult,
+};
+#[cfg(feature = "display")]
+pub use config::PlayerInput;
+#[cfg(feature = "display")]
+pub use keymap::KeyMap;
+#[cfg(feature = "display")]
+pub use run::{
+    run_cpu, run_cpu_recording, run_cpu_with_config, run_cpu_with_hook, run_cpu_with_keymap,
+};
+/// The keys of a [`KeyMap`]
+#[cfg(feature = "display")]
+pub use winit::event::VirtualKeyCode;
+/// The icon of the window, see [`RunConfig::icon`]
+#[cfg(feature = "display")]
+pub use winit::window::Icon;
+pub use screen::{Backend, ButtonName, Buttons, DrawLog, Resolution};
+pub use stepper::Stepper;
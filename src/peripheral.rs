@@ -0,0 +1,63 @@
+use crate::Buttons;
+
+/// A device plugged into one of the controller ports, instead of a standard controller. Use
+/// this to emulate things like the Four Score adapter or a mouse, and connect it with
+/// [`Ppu::connect_peripheral`](crate::Ppu::connect_peripheral).
+///
+/// The cpu talks to it through [`Ppu::write_controller_strobe`](crate::Ppu::write_controller_strobe)
+/// (writes to `0x4016`, which go to both ports) and
+/// [`Ppu::read_controller`](crate::Ppu::read_controller) (reads from `0x4016` or `0x4017`),
+/// or through [`Ppu::read_cpu_bus`](crate::Ppu::read_cpu_bus) and
+/// [`Ppu::write_cpu_bus`](crate::Ppu::write_cpu_bus), which call those.
+///
+/// ```
+/// # use tudelft_nes_ppu::{Buttons, Mirroring, Peripheral, Ppu};
+/// /// A controller with a rapid fire switch that is always on: A is pressed every other read
+/// struct RapidFire {
+///     strobe: bool,
+///     reads: u8,
+///     presses: u32,
+/// }
+///
+/// impl Peripheral for RapidFire {
+///     fn write_strobe(&mut self, value: u8) {
+///         self.strobe = value & 1 != 0;
+///         if self.strobe {
+///             self.reads = 0;
+///         }
+///     }
+///
+///     fn read(&mut self, buttons: Buttons) -> u8 {
+///         let idx = if self.strobe { 0 } else { self.reads };
+///         self.reads = self.reads.saturating_add(1);
+///         let pressed = match idx {
+///             0 => {
+///                 self.presses += 1;
+///                 buttons.a1 && self.presses % 2 == 1
+///             }
+///             1..=7 => buttons.get_by_index(idx),
+///             _ => true,
+///         };
+///         u8::from(pressed)
+///     }
+/// }
+///
+/// let mut ppu = Ppu::new(Mirroring::Horizontal);
+/// ppu.connect_peripheral(0, Box::new(RapidFire { strobe: false, reads: 0, presses: 0 }));
+/// ppu.write_controller_strobe(1);
+/// ppu.write_controller_strobe(0);
+/// assert_eq!(ppu.read_controller(0), 0x40);
+/// ```
+pub trait Peripheral {
+    /// Called for every cpu write to `0x4016`. Bit 0 is the strobe of a standard controller,
+    /// bits 1 and 2 go to the expansion port and are used by some other devices.
+    fn write_strobe(&mut self, value: u8);
+
+    /// Called for every cpu read from the port the device is connected to. `buttons` are the
+    /// buttons pressed by the player right now, see [`Ppu::get_joypad_state`](crate::Ppu::get_joypad_state).
+    ///
+    /// Return the bits the device drives, which are bits 0 to 4 on a real NES. The upper bits
+    /// are ignored and replaced by what is left on the data bus, like
+    /// [`Ppu::read_controller`](crate::Ppu::read_controller) does for a standard controller.
+    fn read(&mut self, buttons: Buttons) -> u8;
+}
@@ -1,5 +1,47 @@
+/// A color as red, green and blue. See [`ColorExt`] for ways to make one.
 pub type Color = (u8, u8, u8);
 
+/// Helpers for [`Color`]. Import this trait to use them:
+///
+/// ```
+/// # use tudelft_nes_ppu::{Color, ColorExt};
+/// assert_eq!(Color::from_hex(0xff8000), Color::rgb(255, 128, 0));
+/// assert_eq!(Color::nes_palette(0x30).to_rgba(), [255, 255, 255, 255]);
+/// ```
+pub trait ColorExt: Sized {
+    /// A color from its red, green and blue parts
+    fn rgb(r: u8, g: u8, b: u8) -> Self;
+
+    /// A color written like in css: `0xRRGGBB`. The highest byte is ignored.
+    fn from_hex(hex: u32) -> Self {
+        let [_, r, g, b] = hex.to_be_bytes();
+        Self::rgb(r, g, b)
+    }
+
+    /// The color as red, green, blue and alpha bytes, like in the frame buffer. It's always
+    /// fully opaque.
+    fn to_rgba(self) -> [u8; 4];
+
+    /// The color the PPU shows for a value in its palette table, from the palette
+    /// [`Ppu::new`](crate::Ppu::new) uses. Only the lowest 6 bits of `index` are used, just
+    /// like the PPU does.
+    fn nes_palette(index: u8) -> Self;
+}
+
+impl ColorExt for Color {
+    fn rgb(r: u8, g: u8, b: u8) -> Self {
+        (r, g, b)
+    }
+
+    fn to_rgba(self) -> [u8; 4] {
+        [self.0, self.1, self.2, 0xff]
+    }
+
+    fn nes_palette(index: u8) -> Self {
+        NES_COLOR_PALLETE[(index & 0x3f) as usize]
+    }
+}
+
 #[allow(clippy::zero_prefixed_literal)]
 pub static NES_COLOR_PALLETE: [Color; 64] = [
     (128, 128, 128),
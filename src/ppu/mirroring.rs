@@ -7,19 +7,18 @@
 /// more ram on the cartridge so all addresses are accessible.
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
 pub enum Mirroring {
-    /// VRAM is set up so the first and third are the same, and the second and fourth are the same
-    Horizontal,
     /// VRAM is set up so the first and second are the same, and the third and fourth are the same
+    Horizontal,
+    /// VRAM is set up so the first and third are the same, and the second and fourth are the same
     Vertical,
-    /// All banks are unique
+    /// All banks are unique. The PPU always has room for this: its vram is 4KB, of which
+    /// the other modes only use the first 2KB.
     FourScreen,
     /// All banks are a copy of the first bank
     ///
-    /// TODO: this feature may not be fully supported by our emulation. Notably, mirroring can't change
-    ///       while some mappers have dynamically changing mirroring modes
+    /// Mappers that use this usually switch between the single screen modes while the game
+    /// runs, see [`Ppu::set_mirroring`](crate::Ppu::set_mirroring).
     SingleScreenLower,
     /// All banks are a copy of the second bank
-    ///
-    /// TODO: this feature may not be fully supported by our emulation. See [`Mirroring::SingleScreenLower`]
     SingleScreenUpper,
 }
@@ -1,20 +1,67 @@
+use crate::apu::Apu;
 use crate::cpu::Cpu;
+use crate::peripheral::Peripheral;
 use crate::ppu::colors::{Color, NES_COLOR_PALLETE};
 use crate::ppu::registers::{
     AddrRegister, ControllerRegister, MaskRegister, OamAddrRegister, ScrollRegister, StatusRegister,
 };
-use crate::screen::ScreenWriter;
-use crate::Buttons;
+use crate::screen::{DrawLog, Resolution, ScreenWriter};
+use crate::{ButtonName, Buttons};
 use crate::{Mirroring, HEIGHT, WIDTH};
-use itertools::Itertools;
 use registers::PpuRegister;
+use std::collections::VecDeque;
 use std::default::Default;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "screenshot")]
+use std::path::Path;
 
 pub mod colors;
 pub mod mirroring;
 pub mod registers;
+pub mod snapshot;
+pub mod state;
+
+/// Returned when data of the wrong size is loaded into the memory of the PPU, for example
+/// with [`Ppu::load_nametables`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemorySizeError {
+    expected: &'static str,
+    actual: usize,
+}
+
+impl MemorySizeError {
+    /// The size of the data that was given, in bytes
+    pub fn actual(&self) -> usize {
+        self.actual
+    }
+}
+
+impl Display for MemorySizeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, got {} bytes", self.expected, self.actual)
+    }
+}
+
+impl Error for MemorySizeError {}
 
 /// Emulating an NTSC PPU chip
+///
+/// Your cpu talks to the PPU through the registers at `0x2000..=0x3FFF`. Forward reads and
+/// writes there to [`Ppu::read_cpu_bus`] and [`Ppu::write_cpu_bus`], which handle the mirroring
+/// of the 8 registers and their side effects, like reading `0x2002` resetting the address
+/// latch and reads from `0x2007` lagging one byte behind. The controllers at `0x4016` and
+/// `0x4017` are read through these too.
+///
+/// ```
+/// # use tudelft_nes_ppu::{Cpu, Ppu};
+/// fn write_memory(ppu: &mut Ppu, cpu: &mut impl Cpu, address: u16, value: u8) {
+///     if ppu.write_cpu_bus(cpu, address, value) {
+///         return;
+///     }
+///     // not a PPU register, so it's ram, a mapper register, etc.
+/// }
+/// ```
 pub struct Ppu {
     /// how many lines we've drawn. After 240, an NMI is given to the cpu
     /// and only at 262 does it reset to 0
@@ -22,14 +69,14 @@ pub struct Ppu {
     /// how many cycles we've had on this line. Resets after reaching 341.
     /// Note that the screen is only 256 pixels wide. So There's a small h-blank period.
     line_progress: usize,
+    /// how many frames have been completed since the PPU was created or reset
+    frame_count: u64,
 
     controller_register: ControllerRegister,
     mask_register: MaskRegister,
     status_register: StatusRegister,
     addr: AddrRegister,
-    addr_new_nametable: u16,
     scroll: ScrollRegister,
-    scroll_access: ScrollRegister,
 
     oam_addr: OamAddrRegister,
 
@@ -53,25 +100,138 @@ pub struct Ppu {
 
     pub mirroring: Mirroring,
 
+    /// The 64 colors the values in the palette table refer to.
+    palette: [Color; 64],
+    /// When set, layers disabled through the mask register are drawn as a checkerboard.
+    debug_layers: bool,
+    /// Whether an NMI was given at the start of vblank, until taken by [`Ppu::take_nmi`]
+    nmi_pending: bool,
+    /// How many NMIs were given since the PPU was created, for [`RunStats`](crate::RunStats)
+    pub(crate) nmi_count: usize,
+    /// Calls to [`Cpu::tick`] in the frame that is being drawn, counted by the run loop
+    pub(crate) ticks_this_frame: usize,
+    /// Calls to [`Cpu::tick`] in the previous frame
+    ticks_last_frame: usize,
+    /// The line sprite 0 first hit the background on, this frame
+    sprite_zero_hit_scanline: Option<u16>,
+    /// Colors shown instead of what a palette table entry refers to, see
+    /// [`Ppu::debug_force_palette`].
+    forced_palette: [Option<Color>; 32],
+
     pub(crate) buttons: Buttons,
+    /// Button presses and releases that haven't been taken by [`Ppu::button_events`] yet.
+    button_events: VecDeque<(ButtonName, bool)>,
+    /// How often every button was pressed, indexed like [`Buttons::get_by_index`]
+    button_press_counts: [u32; 16],
+    /// The strobe bit last written to `0x4016`. While it is set, the controllers keep
+    /// loading the state of their buttons.
+    controller_strobe: bool,
+    /// How many bits were read from each controller since the strobe was released
+    controller_reads: [u8; 2],
+    /// Devices connected instead of a standard controller, see [`Ppu::connect_peripheral`]
+    peripherals: [Option<Box<dyn Peripheral + Send>>; 2],
+    /// Whether a Zapper is plugged into the second port, see [`Ppu::connect_zapper`]
+    zapper_connected: bool,
+    /// How many frames in a row passed without any input
+    idle_frames: u64,
+    /// The pixel that was last double clicked, until taken by [`Ppu::take_double_click`].
+    pub(crate) double_click: Option<(usize, usize)>,
+    /// The pixel the light gun points at, if it points at the screen
+    pointer: Option<(usize, usize)>,
+    /// The color last drawn at `pointer`
+    pointed_color: Option<Color>,
+    /// The value of `dots` when `pointed_color` was drawn
+    pointed_drawn_at: u64,
+    /// How many dots the PPU ran since it was created, to know how long ago a pixel was drawn
+    dots: u64,
+    /// How bright a pixel has to be for the Zapper to see it, see [`Ppu::set_zapper_threshold`]
+    zapper_threshold: u8,
+    /// What the frame buffer is filled with before every frame, see [`Ppu::set_clear_color`]
+    clear_color: Option<Color>,
+    /// Set by [`Ppu::pause`], the run loop waits for a button press while this is set
+    paused: bool,
+
+    /// Bytes sent over the link cable, see [`run_two_cpus`](crate::run_two_cpus)
+    pub(crate) link_out: VecDeque<u8>,
+    /// Bytes received over the link cable
+    pub(crate) link_in: VecDeque<u8>,
+
+    /// The sound chip. It's not part of the PPU on the real NES, but it sits on the same bus
+    /// and is clocked along with it.
+    pub(crate) apu: Apu,
+
+    /// Where the frames are drawn to. When there is no window (headless mode) this
+    /// is a dummy that only keeps the frame buffer.
+    pub(crate) screen: ScreenWriter,
+    /// A copy of the frame as it was at the start of the last vblank
+    last_frame: Vec<u8>,
+}
+
+/// A small pseudo-random number generator, for the power-on state of [`Ppu::new_seeded`].
+/// Its output is part of the behaviour of that function, so it must never change.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let random = self.next().to_le_bytes();
+            chunk.copy_from_slice(&random[..chunk.len()]);
+        }
+    }
 }
 
 impl Ppu {
-    /// Creates a new PPU. The mirroring mode needs to be given and is constant
-    /// for the lifetime of the emulator. Some real-world memory mappers could howerver
-    /// change this in the middle of running a game. This is currently *not* supported
-    /// by the emulator.
+    /// Creates a new PPU. The mirroring mode needs to be given. Some real-world memory mappers
+    /// change it in the middle of running a game, which can be emulated with
+    /// [`Ppu::set_mirroring`].
+    ///
+    /// All memory starts out as zeros: vram, oam and the palette table, and so do all
+    /// registers. The headless run functions use this too, so their results never depend on
+    /// chance. See [`Ppu::new_seeded`] for memory that is random like on a real NES.
     pub fn new(mirroring: Mirroring) -> Self {
+        Self::new_with_palette(mirroring, NES_COLOR_PALLETE)
+    }
+
+    /// Like [`Ppu::new`], but vram, oam and the palette table are filled with pseudo-random
+    /// bytes generated from `seed`, like the memory of a real NES which has no defined
+    /// contents at power-on. Some games (accidentally) depend on it. The same seed always
+    /// gives the same contents, so a bug found this way can be reproduced. The registers
+    /// still start out as zeros.
+    pub fn new_seeded(mirroring: Mirroring, seed: u64) -> Self {
+        let mut ppu = Self::new(mirroring);
+        let mut rng = SplitMix64(seed);
+
+        rng.fill(&mut ppu.vram);
+        rng.fill(&mut ppu.oam);
+        rng.fill(&mut ppu.palette_table);
+        // the palette table only stores 6 bits per entry
+        for entry in &mut ppu.palette_table {
+            *entry &= 0x3f;
+        }
+
+        ppu
+    }
+
+    /// Like [`Ppu::new`], but the PPU will use a custom set of 64 colors instead of the
+    /// default NES palette. Every byte in the palette table indexes into this array.
+    pub fn new_with_palette(mirroring: Mirroring, palette: [Color; 64]) -> Self {
         Self {
             scanline: 0,
             line_progress: 0,
+            frame_count: 0,
             controller_register: ControllerRegister::default(),
             mask_register: MaskRegister::default(),
             status_register: StatusRegister::default(),
             addr: AddrRegister::default(),
-            addr_new_nametable: 0x2000,
             scroll: ScrollRegister::default(),
-            scroll_access: ScrollRegister::default(),
             oam_addr: OamAddrRegister::default(),
             scroll_addr_latch: true,
             palette_table: [0; 32],
@@ -81,10 +241,149 @@ impl Ppu {
             bus: 0,
             data_buffer: 0,
             mirroring,
+            palette,
+            debug_layers: false,
+            forced_palette: [None; 32],
+            sprite_zero_hit_scanline: None,
+            nmi_pending: false,
+            nmi_count: 0,
+            ticks_this_frame: 0,
+            ticks_last_frame: 0,
             buttons: Buttons::default(),
+            button_events: VecDeque::new(),
+            button_press_counts: [0; 16],
+            controller_strobe: false,
+            controller_reads: [0; 2],
+            peripherals: [None, None],
+            zapper_connected: false,
+            idle_frames: 0,
+            double_click: None,
+            pointer: None,
+            pointed_color: None,
+            pointed_drawn_at: 0,
+            dots: 0,
+            zapper_threshold: 200,
+            clear_color: None,
+            paused: false,
+            link_out: VecDeque::new(),
+            link_in: VecDeque::new(),
+            apu: Apu::default(),
+            screen: ScreenWriter::dummy(Resolution::NES),
+            last_frame: vec![0; Resolution::NES.buffer_len()],
+        }
+    }
+
+    /// Like [`Ppu::new`], but with a frame of `resolution` instead of the size of the NES
+    /// screen. The PPU draws in the top left corner and leaves the rest to you, see
+    /// [`Resolution`].
+    ///
+    /// # Panics
+    /// When `resolution` is smaller than [`Resolution::NES`], or wider or higher than 65536
+    /// pixels.
+    pub fn with_resolution(mirroring: Mirroring, resolution: Resolution) -> Self {
+        assert!(
+            resolution.width >= WIDTH && resolution.height >= HEIGHT,
+            "the resolution has to be at least {WIDTH}x{HEIGHT}"
+        );
+        assert!(
+            resolution.width <= 1 << 16 && resolution.height <= 1 << 16,
+            "the resolution can be at most 65536x65536"
+        );
+
+        let mut ppu = Self::new(mirroring);
+        ppu.screen = ScreenWriter::dummy(resolution);
+        ppu.last_frame = vec![0; resolution.buffer_len()];
+        ppu
+    }
+
+    /// The size of the frame, see [`Ppu::with_resolution`]
+    pub fn resolution(&self) -> Resolution {
+        self.screen.resolution()
+    }
+
+    /// Resets the PPU to its power-on state, like pulling the reset line of the real chip.
+    /// All registers, vram, oam, the palette table and the scanline counters are cleared.
+    ///
+    /// The mirroring mode, the color palette, debug settings, the screen, the buttons
+    /// pressed by the player (with the events and counts of [`Ppu::button_events`] and
+    /// [`Ppu::button_press_counts`]), the link cable and the NMI count are kept, since those
+    /// are not part of the PPU itself.
+    pub fn reset(&mut self) {
+        let fresh = Self::new_with_palette(self.mirroring, self.palette);
+        let old = std::mem::replace(self, fresh);
+
+        self.buttons = old.buttons;
+        self.button_events = old.button_events;
+        self.button_press_counts = old.button_press_counts;
+        self.controller_strobe = old.controller_strobe;
+        self.controller_reads = old.controller_reads;
+        self.peripherals = old.peripherals;
+        self.zapper_connected = old.zapper_connected;
+        self.debug_layers = old.debug_layers;
+        self.forced_palette = old.forced_palette;
+        self.zapper_threshold = old.zapper_threshold;
+        self.clear_color = old.clear_color;
+        self.nmi_count = old.nmi_count;
+        self.link_out = old.link_out;
+        self.link_in = old.link_in;
+        self.apu = old.apu;
+        self.apu.reset();
+        self.screen = old.screen;
+        self.last_frame = old.last_frame;
+    }
+
+    /// Changes the mirroring mode, like mappers such as MMC1 and MMC3 do. This takes effect
+    /// right away, also in the middle of a frame: every access to the nametables looks at the
+    /// current mode. The contents of vram stay the same, so nametables that were copies of
+    /// each other may now show different data.
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+
+    /// Debugging aid. When enabled, the background and sprite layers are drawn as a faint
+    /// checkerboard whenever they are disabled in the mask register (0x2001), so it is
+    /// visible on screen *that* a layer is turned off. Off by default.
+    pub fn set_debug_layers(&mut self, enabled: bool) {
+        self.debug_layers = enabled;
+    }
+
+    /// Debugging aid. Shows `color` wherever entry `slot` (0 to 31) of the palette table is
+    /// used, without changing what the cpu reads back from it. Forcing a slot to a color that
+    /// stands out, like magenta, shows where on screen it is used. `None` removes the override.
+    pub fn debug_force_palette(&mut self, slot: u8, color: Option<Color>) {
+        self.forced_palette[(slot & 0x1f) as usize] = color;
+    }
+
+    /// Fills the nametables (`0x2000` and up) with `data`, as if the cpu wrote it there through
+    /// the data register. This way a test can render a known scene right away. `data` is 1 to
+    /// 4 nametables of 1024 bytes each, including their attribute tables. Writes to mirrored
+    /// nametables end up in the same memory, just like on a real NES.
+    ///
+    /// The pattern tables are not part of the PPU: it reads them from your cartridge through
+    /// [`Cpu::ppu_read_chr_rom`].
+    pub fn load_nametables(&mut self, data: &[u8]) -> Result<(), MemorySizeError> {
+        if !matches!(data.len(), 1024 | 2048 | 3072 | 4096) {
+            return Err(MemorySizeError {
+                expected: "1 to 4 nametables of 1024",
+                actual: data.len(),
+            });
+        }
+
+        for (offset, &byte) in data.iter().enumerate() {
+            let addr = self.mirror_address(0x2000 + offset as u16);
+            self.vram[addr as usize - 0x2000] = byte;
         }
+        Ok(())
     }
 
+    /// Fills the palette table (`0x3f00..=0x3f1f`) with `data`, which has to be 32 bytes.
+    pub fn load_palette_table(&mut self, data: &[u8]) -> Result<(), MemorySizeError> {
+        self.palette_table = data.try_into().map_err(|_| MemorySizeError {
+            expected: "32",
+            actual: data.len(),
+        })?;
+        Ok(())
+    }
 
     fn vram_read_mirrored(&self, addr: u16) -> u8 {
         self.vram[(self.mirror_address(addr) - 0x2000) as usize]
@@ -135,6 +434,283 @@ impl Ppu {
         self.buttons
     }
 
+    /// A cpu write to `0x4016`. Bit 0 is the strobe: while it is 1, both controllers keep
+    /// loading which buttons are pressed, and when it goes back to 0 they can be read one
+    /// button at a time with [`Ppu::read_controller`].
+    pub fn write_controller_strobe(&mut self, value: u8) {
+        self.controller_strobe = value & 1 != 0;
+        if self.controller_strobe {
+            self.controller_reads = [0; 2];
+        }
+        for peripheral in self.peripherals.iter_mut().flatten() {
+            peripheral.write_strobe(value);
+        }
+    }
+
+    /// A cpu read from `0x4016` (`controller` 0) or `0x4017` (`controller` 1), which shifts
+    /// out the next button of that controller in bit 0: A, B, Select, Start, Up, Down, Left
+    /// and Right. After those 8 reads it returns 1, like an official controller. While the
+    /// strobe is set every read returns A. The upper bits are what is usually left on the
+    /// data bus, which is `0x40`.
+    ///
+    /// When a [`Peripheral`] is [connected](Ppu::connect_peripheral) to the port, it is read
+    /// instead, and so is the Zapper when it's [connected](Ppu::connect_zapper).
+    ///
+    /// # Panics
+    /// When `controller` is not 0 or 1
+    pub fn read_controller(&mut self, controller: usize) -> u8 {
+        if controller == 1 && self.zapper_connected {
+            return self.read_zapper();
+        }
+        if let Some(peripheral) = &mut self.peripherals[controller] {
+            return 0x40 | (peripheral.read(self.buttons) & 0x1f);
+        }
+
+        let reads = &mut self.controller_reads[controller];
+        let pressed = if self.controller_strobe {
+            self.buttons.get_by_index(controller as u8 * 8)
+        } else if *reads < 8 {
+            let pressed = self.buttons.get_by_index(controller as u8 * 8 + *reads);
+            *reads += 1;
+            pressed
+        } else {
+            true
+        };
+
+        0x40 | u8::from(pressed)
+    }
+
+    /// Connects a device to controller port `port` (0 for `0x4016`, 1 for `0x4017`), instead of
+    /// a standard controller. From now on, [`Ppu::read_controller`] reads it, and it sees every
+    /// write to [`Ppu::write_controller_strobe`]. Replaces the device that was connected before.
+    ///
+    /// The device stays connected when the PPU is [reset](Ppu::reset). It isn't part of a
+    /// [save state](Ppu::save_state), which only has the strobe and the standard controllers.
+    ///
+    /// # Panics
+    /// When `port` is not 0 or 1
+    pub fn connect_peripheral(&mut self, port: usize, peripheral: Box<dyn Peripheral + Send>) {
+        self.peripherals[port] = Some(peripheral);
+        if port == 1 {
+            self.zapper_connected = false;
+        }
+    }
+
+    /// Disconnects the device connected with [`Ppu::connect_peripheral`] (or the Zapper) and
+    /// returns it, so the port has a standard controller again.
+    ///
+    /// # Panics
+    /// When `port` is not 0 or 1
+    pub fn disconnect_peripheral(&mut self, port: usize) -> Option<Box<dyn Peripheral + Send>> {
+        if port == 1 {
+            self.zapper_connected = false;
+        }
+        self.peripherals[port].take()
+    }
+
+    /// Plugs the Zapper (the light gun) into the second controller port, like games such as
+    /// Duck Hunt expect. From now on, reads from `0x4017` return [`Ppu::read_zapper`] instead
+    /// of the second controller. Replaces the device that was connected to that port before,
+    /// and stays connected like one, see [`Ppu::connect_peripheral`].
+    pub fn connect_zapper(&mut self) {
+        self.peripherals[1] = None;
+        self.zapper_connected = true;
+    }
+
+    /// Takes all button presses (`true`) and releases (`false`) that happened since the last
+    /// call, oldest first. This lets you react to a button changing instead of checking the
+    /// state of every button all the time.
+    ///
+    /// At most [`Ppu::MAX_BUTTON_EVENTS`] events are kept, if there are more the oldest
+    /// ones are dropped.
+    pub fn button_events(&mut self) -> Vec<(ButtonName, bool)> {
+        std::mem::take(&mut self.button_events).into()
+    }
+
+    /// How many events [`Ppu::button_events`] remembers, so they don't pile up forever
+    /// when they are never taken.
+    pub const MAX_BUTTON_EVENTS: usize = 256;
+
+    /// How many times every button was pressed since the PPU was created, indexed like
+    /// [`Buttons::get_by_index`]. Holding a button down counts as a single press.
+    pub fn button_press_counts(&self) -> [u32; 16] {
+        self.button_press_counts
+    }
+
+    /// How many frames in a row went by without the player touching any button. This
+    /// can be used to go back to a demo screen after a while. Pressing or releasing a
+    /// button, or holding one down, resets it to 0.
+    pub fn idle_frames(&self) -> u64 {
+        self.idle_frames
+    }
+
+    /// Takes the position of the last double click with the mouse, if there was one since the
+    /// last call. The position is in pixels on the NES screen, the same pixels the light gun
+    /// points at. Double clicks outside of the frame, like on the black bars around it, are
+    /// ignored.
+    pub fn take_double_click(&mut self) -> Option<(usize, usize)> {
+        self.double_click.take()
+    }
+
+    /// The pixel on the NES screen the mouse (the light gun) points at, or `None` when it is
+    /// outside of the frame or there is no window.
+    pub fn pointed_pixel_position(&self) -> Option<(usize, usize)> {
+        self.pointer
+    }
+
+    /// The color of the pixel the mouse points at, as it was last drawn. This is what the
+    /// light gun sees, so a game can check for the white flash of a target itself. `None`
+    /// when the mouse doesn't point at the screen, or nothing was drawn there since.
+    pub fn pointed_pixel_color(&self) -> Option<Color> {
+        self.pointed_color
+    }
+
+    /// How long the Zapper keeps seeing a pixel after it was drawn: the light of a CRT fades
+    /// quickly, so a real Zapper only sees it for about 26 scanlines.
+    const LIGHT_SENSE_DOTS: u64 = 26 * 341;
+
+    /// Whether the Zapper (the light gun) sees light right now, like the light sense bit
+    /// of a real Zapper. That is when the pixel it points at is brighter than
+    /// [the threshold](Ppu::set_zapper_threshold), and was drawn less than about 26 scanlines
+    /// ago. Games check this right after the picture was drawn at the position of a target.
+    pub fn zapper_light_detected(&self) -> bool {
+        let Some(color) = self.pointed_color else {
+            return false;
+        };
+
+        self.dots.saturating_sub(self.pointed_drawn_at) < Self::LIGHT_SENSE_DOTS
+            && self.bright_enough(color)
+    }
+
+    /// Whether `color` is bright enough for the Zapper to see, see
+    /// [`Ppu::set_zapper_threshold`]
+    fn bright_enough(&self, (r, g, b): Color) -> bool {
+        let luminance = 0.299 * f32::from(r) + 0.587 * f32::from(g) + 0.114 * f32::from(b);
+        luminance > f32::from(self.zapper_threshold)
+    }
+
+    /// Sets how bright (in luminance, from 0 to 255) a pixel has to be for
+    /// [`Ppu::zapper_light_detected`]. Some games flash their targets darker than others.
+    /// Defaults to 200.
+    pub fn set_zapper_threshold(&mut self, threshold: u8) {
+        self.zapper_threshold = threshold;
+    }
+
+    /// What a cpu read from `0x4017` returns with a Zapper plugged into the second controller
+    /// port, instead of [`Ppu::read_controller`]. Bit 3 is 0 when
+    /// [light is detected](Ppu::zapper_light_detected), bit 4 is 1 while the trigger is pulled.
+    /// The upper bits are what is usually left on the data bus, `0x40`, like for a controller.
+    /// With [`Ppu::connect_zapper`], [`Ppu::read_cpu_bus`] returns this for `0x4017`.
+    pub fn read_zapper(&self) -> u8 {
+        let light = if self.zapper_light_detected() {
+            0
+        } else {
+            0b0000_1000
+        };
+        let trigger = if self.buttons.trigger { 0b0001_0000 } else { 0 };
+
+        0x40 | light | trigger
+    }
+
+    /// Makes `key` press `button` in the window from now on, for example from a controls menu
+    /// in the game. This changes the [`KeyMap`](crate::KeyMap) the window started with (or the
+    /// one of the player on the keyboard with that button, see
+    /// [`RunConfig::players`](crate::RunConfig::players)). It takes effect when the window
+    /// handles its next event, which is at the latest when a key is pressed. Without a window
+    /// this does nothing.
+    ///
+    /// A key presses at most one button, so when it was bound before, it stops pressing the
+    /// button it pressed until now. The other keys bound to `button` keep pressing it too. The
+    /// keys with a fixed meaning, like tab and F11, keep doing that as well.
+    #[cfg(feature = "display")]
+    pub fn rebind_key(&self, button: ButtonName, key: crate::VirtualKeyCode) {
+        self.screen
+            .send_to_window(crate::screen::Message::RebindKey(button, key));
+    }
+
+    /// The size of the inside of the window in physical pixels, which changes when the player
+    /// resizes the window or makes it fullscreen. `None` without a window, like in headless
+    /// mode or with a custom [`Backend`](crate::Backend).
+    pub fn current_window_size(&self) -> Option<(u32, u32)> {
+        self.screen.window_size()
+    }
+
+    /// Changes the title of the window, for example to show the score. Without a window this
+    /// does nothing. The window starts with [`RunConfig::title`](crate::RunConfig::title).
+    pub fn set_window_title(&self, title: &str) {
+        self.screen.set_title(title);
+    }
+
+    /// Starts recording every pixel the PPU draws, in order, to check the rendering in tests.
+    /// The frame buffer keeps being drawn to as well.
+    ///
+    /// This replaces the screen, so it's meant for a PPU without a window, like the one of a
+    /// [`Stepper`](crate::Stepper).
+    ///
+    /// ```
+    /// # use tudelft_nes_ppu::{Mirroring, Stepper};
+    /// let mut stepper = Stepper::new(Mirroring::Horizontal);
+    /// let log = stepper.ppu_mut().record_draw_calls();
+    /// // ... step through a frame ...
+    /// for (x, y, color) in log.take() {
+    ///     assert!(x < 256 && y < 240);
+    /// }
+    /// ```
+    pub fn record_draw_calls(&mut self) -> DrawLog {
+        let (writer, log) = ScreenWriter::recording(self.resolution());
+        self.screen = writer;
+        log
+    }
+
+    /// Pauses the emulation, for example when the game opens a menu. After the current
+    /// [`Cpu::tick`] returns, nothing runs until the player presses a button on the
+    /// window. That press then [resumes](Ppu::resume) the emulation and is seen by the cpu
+    /// as usual, so it can just check for it. Losing and regaining focus doesn't resume.
+    ///
+    /// Without a window (in headless mode) there is no player to press a button, so pausing
+    /// does nothing there.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Undoes [`Ppu::pause`], before the run loop got to pause.
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Whether the cpu [paused](Ppu::pause) the emulation
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Sends a byte to the other NES over the link cable. This only does something when
+    /// running with [`run_two_cpus`](crate::run_two_cpus), otherwise nobody is listening.
+    pub fn link_send(&mut self, byte: u8) {
+        self.link_out.push_back(byte);
+    }
+
+    /// Receives the oldest byte the other NES sent over the link cable that wasn't received yet.
+    pub fn link_recv(&mut self) -> Option<u8> {
+        self.link_in.pop_front()
+    }
+
+    pub(crate) fn handle_button(&mut self, name: ButtonName, pressed: bool) {
+        if self.button_events.len() >= Self::MAX_BUTTON_EVENTS {
+            self.button_events.pop_front();
+        }
+        self.button_events.push_back((name, pressed));
+        self.idle_frames = 0;
+
+        if let Some(idx) = name.index() {
+            if pressed && !self.buttons.get_by_index(idx) {
+                let count = &mut self.button_press_counts[idx as usize];
+                *count = count.saturating_add(1);
+            }
+        }
+
+        self.buttons.set(name, pressed);
+    }
+
     /// Write to a register of the PPU. This is supposed to be called from the CPU when a write occurs
     /// to one of the addresses as defined in the spec (and also mentioned in the docs of [`PpuRegister`])
     pub fn write_ppu_register(&mut self, cpu: &mut impl Cpu, register: PpuRegister, value: u8) {
@@ -143,13 +719,7 @@ impl Ppu {
         match register {
             PpuRegister::Controller => {
                 self.controller_register.write(value);
-                self.addr_new_nametable = match value & 0b11 {
-                    0b00 => 0x2000,
-                    0b01 => 0x2400,
-                    0b10 => 0x2800,
-                    0b11 => 0x2c00,
-                    _ => unreachable!(),
-                }
+                self.scroll.write_controller(value);
             }
             PpuRegister::Mask => self.mask_register.write(value),
             PpuRegister::Status => { /* Nothing */ }
@@ -161,19 +731,11 @@ impl Ppu {
                 self.oam_addr.addr = self.oam_addr.addr.wrapping_add(1);
             }
             PpuRegister::Scroll => {
-                self.scroll_access.write(value, !self.scroll_addr_latch);
+                self.scroll.write_scroll(value, self.scroll_addr_latch);
                 self.scroll_addr_latch = !self.scroll_addr_latch;
             }
             PpuRegister::Address => {
-                if self.scroll_addr_latch {
-                    self.addr_new_nametable = match value & 0b1100 {
-                        0b0000 => 0x2000,
-                        0b0100 => 0x2400,
-                        0b1000 => 0x2800,
-                        0b1100 => 0x2c00,
-                        _ => unreachable!(),
-                    }
-                }
+                self.scroll.write_address(value, self.scroll_addr_latch);
                 self.addr.write(value, self.scroll_addr_latch);
                 self.scroll_addr_latch = !self.scroll_addr_latch;
             }
@@ -206,11 +768,84 @@ impl Ppu {
         }
     }
 
+    /// Handles a cpu write to `address` if it is one of the PPU registers, so your cpu doesn't
+    /// need to decode them itself (see [`PpuRegister::from_address`]). Returns whether the
+    /// address belonged to the PPU. If not, the write is for another part of your memory map.
+    ///
+    /// The controller strobe at `0x4016` is handled too (see [`Ppu::write_controller_strobe`]).
+    /// So are the sound registers, see [`Apu::write_register`]. Writes to `0x4014` (OAM DMA)
+    /// are *not* handled here, since they need a page of cpu memory. Pass that to
+    /// [`Ppu::write_oam_dma`] instead.
+    pub fn write_cpu_bus(&mut self, cpu: &mut impl Cpu, address: u16, value: u8) -> bool {
+        if address == 0x4016 {
+            self.write_controller_strobe(value);
+            return true;
+        }
+        if self.apu.write_register(address, value) {
+            return true;
+        }
+
+        match PpuRegister::from_address(address) {
+            Some(register) => {
+                self.write_ppu_register(cpu, register, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Handles a cpu read from `address` if it is one of the PPU registers, a controller
+    /// (`0x4016` and `0x4017`, see [`Ppu::read_controller`]) or the sound status at `0x4015`
+    /// (see [`Apu::read_status`]), like [`Ppu::write_cpu_bus`] does for writes. Returns `None`
+    /// when the address doesn't belong to the PPU.
+    pub fn read_cpu_bus(&mut self, address: u16, cpu: &impl Cpu) -> Option<u8> {
+        match address {
+            0x4015 => return Some(self.apu.read_status()),
+            0x4016 => return Some(self.read_controller(0)),
+            0x4017 => return Some(self.read_controller(1)),
+            _ => {}
+        }
+
+        let register = PpuRegister::from_address(address)?;
+        Some(self.read_ppu_register(register, cpu))
+    }
+
+    /// The sound chip, to take its samples with [`Apu::take_samples`]
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    /// The sound chip, see [`Ppu::apu`]
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
     /// Read from a register of the PPU. This is supposed to be called from the CPU when a read occurs
     /// to one of the addresses as defined in the spec (and also mentioned in the docs of [`PpuRegister`])
     ///
     /// We ask for a reference to the cpu here, since we sometimes need to read from the cartridge.
     ///
+    /// Like on the real PPU, registers that can't be read return the last value that was
+    /// written to any register (the "open bus"), and so do the lower 5 bits of the status
+    /// register, which only has 3 flags:
+    ///
+    /// ```
+    /// # use tudelft_nes_ppu::{Cpu, Mirroring, Ppu, PpuRegister};
+    /// # struct NoCpu;
+    /// # impl Cpu for NoCpu {
+    /// #     type TickError = std::convert::Infallible;
+    /// #     fn tick(&mut self, _: &mut Ppu) -> Result<(), Self::TickError> { Ok(()) }
+    /// #     fn ppu_read_chr_rom(&self, _: u16) -> u8 { 0 }
+    /// #     fn non_maskable_interrupt(&mut self) {}
+    /// # }
+    /// # let mut cpu = NoCpu;
+    /// let mut ppu = Ppu::new(Mirroring::Horizontal);
+    /// ppu.write_ppu_register(&mut cpu, PpuRegister::OamAddress, 0b1011_0101);
+    /// let status = ppu.read_ppu_register(PpuRegister::Status, &cpu);
+    /// // no flags are set yet
+    /// assert_eq!(status, 0b0001_0101);
+    /// ```
+    ///
     /// # Panics
     /// Can panic when address in data register is out of bounds
     pub fn read_ppu_register(&mut self, register: PpuRegister, cpu: &impl Cpu) -> u8 {
@@ -261,33 +896,338 @@ impl Ppu {
         self.bus
     }
 
+    /// The scanline the PPU is at, from 0 to 261. Lines 0 to 239 are visible, 240 is idle,
+    /// vblank is 241 to 260 and 261 is the pre-render line, after which the next frame starts
+    /// at line 0 again. The scanline advances whenever [`Ppu::dot`] wraps around.
+    pub fn scanline(&self) -> u16 {
+        self.scanline as u16
+    }
+
+    /// The dot (PPU cycle) on the current [scanline](Ppu::scanline), from 0 to 340. On the
+    /// visible lines, dots 0 to 255 each draw the pixel at that x position.
+    ///
+    /// Every cpu cycle, [`Cpu::tick`] is called first and then the PPU runs
+    /// [`Cpu::DOTS_PER_TICK`] (usually 3) dots. So while [`Cpu::tick`] runs, this is where the
+    /// PPU was left by the previous cycle, and a register write during the tick takes effect
+    /// from the next dot on.
+    pub fn dot(&self) -> u16 {
+        self.line_progress as u16
+    }
+
+    /// Whether the PPU is in vblank: from the start of scanline 241 until the end of the
+    /// pre-render scanline 261, after which the next frame starts at scanline 0. Unlike the
+    /// vblank flag in the status register, this is not cleared by reading `0x2002`.
+    pub fn in_vblank(&self) -> bool {
+        self.scanline >= 241
+    }
+
+    /// Returns `true` once after every vblank in which the PPU gave the cpu an NMI (through
+    /// [`Cpu::non_maskable_interrupt`]), so the cpu can also poll for it instead. The NMI is
+    /// only given when it is enabled in the controller register (`0x2000`).
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    /// How many frames the PPU has completed since it was created (or [reset](Ppu::reset)).
+    /// This goes up by one at the start of every vblank.
+    ///
+    /// The count only depends on how many cycles were emulated, never on how fast the host
+    /// computer runs them. So headless runs that get the same input at the same cycles see
+    /// the same frame count at the same cycles, which makes it safe to seed a random number
+    /// generator from it in reproducible tests.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// The RGBA frame buffer the PPU draws to, `WIDTH * HEIGHT * 4` bytes in row-major order
+    /// (or larger, see [`Ppu::with_resolution`]).
+    ///
+    /// This works the same with and without a window, so it can be inspected in headless tests.
+    /// It is the buffer the PPU draws into, so mid-frame it contains part of the new frame and
+    /// part of the previous one. It is copied to the window at the start of every vblank.
+    /// Borrowing it is free: nothing is copied and the window is not locked.
+    pub fn frame(&self) -> &[u8] {
+        self.screen.frame()
+    }
+
+    /// The last fully rendered frame, as RGBA bytes like [`Ppu::frame`]. Unlike that buffer,
+    /// this doesn't change while the next frame is being drawn: it is updated at the start
+    /// of every vblank, when the frame is also shown in the window. Before the first vblank,
+    /// it is all zeroes.
+    pub fn get_frame(&self) -> &[u8] {
+        &self.last_frame
+    }
+
+    /// Fills the frame buffer with `color` right before the PPU starts drawing every frame,
+    /// and right away. This is a debugging aid: the PPU draws every pixel of the screen in
+    /// every frame, so the color only shows where it hasn't drawn yet. Looking at
+    /// [`Ppu::frame`] in the middle of a frame (with a [`Stepper`](crate::Stepper)) then
+    /// shows exactly how far the PPU got. Without this, the frame buffer is never cleared and
+    /// the rest of the previous frame shows there, which starts out black.
+    pub fn set_clear_color(&mut self, color: Color) {
+        self.clear_color = Some(color);
+        self.screen.clear(color);
+    }
+
+    /// Every pixel of the last fully rendered frame (see [`Ppu::get_frame`]) as `(x, y, color)`,
+    /// row by row. This borrows the frame, nothing is copied.
+    ///
+    /// ```
+    /// # use tudelft_nes_ppu::{Mirroring, Ppu};
+    /// let ppu = Ppu::new(Mirroring::Horizontal);
+    /// let black = ppu.pixels().filter(|&(_, _, color)| color == (0, 0, 0)).count();
+    /// assert_eq!(black, 256 * 240);
+    /// ```
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize, Color)> + '_ {
+        self.last_frame
+            .chunks_exact(4)
+            .enumerate()
+            .map(|(idx, rgba)| {
+                let x = idx % self.resolution().width as usize;
+                let y = idx / self.resolution().width as usize;
+                (x, y, (rgba[0], rgba[1], rgba[2]))
+            })
+    }
+
+    /// Saves the last fully rendered frame (see [`Ppu::get_frame`]) as a PNG image.
+    #[cfg(feature = "screenshot")]
+    pub fn save_frame_png(&self, path: impl AsRef<Path>) -> image::ImageResult<()> {
+        image::save_buffer(
+            path,
+            self.get_frame(),
+            self.resolution().width,
+            self.resolution().height,
+            image::ColorType::Rgba8,
+        )
+    }
+
+    /// Copies the frame (see [`Ppu::frame`]) into `out`, so a buffer can be reused for every
+    /// frame instead of allocating a new one.
+    ///
+    /// # Panics
+    /// When `out` is not exactly as long as the frame, `WIDTH * HEIGHT * 4` bytes unless a
+    /// larger [`Resolution`] is used
+    pub fn render_into(&self, out: &mut [u8]) {
+        assert_eq!(
+            out.len(),
+            self.frame().len(),
+            "output buffer has a different size than the frame"
+        );
+        out.copy_from_slice(self.frame());
+    }
+
+    /// The color of the pixel at (`x`, `y`) in the frame, or `None` when the coordinates are
+    /// outside of the screen.
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<Color> {
+        let resolution = self.resolution();
+        if !resolution.contains(x, y) {
+            return None;
+        }
+
+        let offset = 4 * (y * resolution.width as usize + x);
+        let pixel = &self.frame()[offset..offset + 3];
+        Some((pixel[0], pixel[1], pixel[2]))
+    }
+
+    /// The colors of a `w` by `h` rectangle of the frame, starting at (`x`, `y`), row by row.
+    /// This is faster than calling [`Ppu::get_pixel`] for every pixel, for example to average
+    /// the brightness of an area.
+    ///
+    /// The rectangle is clipped to the screen, so fewer than `w * h` colors are returned
+    /// when it doesn't fit.
+    pub fn get_region(&self, x: usize, y: usize, w: usize, h: usize) -> Vec<Color> {
+        let resolution = self.resolution();
+        if !resolution.contains(x, y) {
+            return Vec::new();
+        }
+        let width = resolution.width as usize;
+        let x_end = x.saturating_add(w).min(width);
+        let y_end = y.saturating_add(h).min(resolution.height as usize);
+
+        let mut region = Vec::with_capacity((x_end - x) * (y_end - y));
+        for row in y..y_end {
+            let start = 4 * (row * width + x);
+            let end = 4 * (row * width + x_end);
+            region.extend(
+                self.frame()[start..end]
+                    .chunks_exact(4)
+                    .map(|p| (p[0], p[1], p[2])),
+            );
+        }
+        region
+    }
+
+    /// The smallest rectangle containing every pixel of the frame that is not the backdrop
+    /// color, as `(min_x, min_y, max_x, max_y)` (all inclusive). `None` when the whole frame
+    /// is backdrop. Useful to crop screenshots to the interesting part.
+    pub fn content_bounds(&self) -> Option<(usize, usize, usize, usize)> {
+        let backdrop = self.backdrop_color();
+        let backdrop = [backdrop.0, backdrop.1, backdrop.2];
+
+        let width = self.resolution().width as usize;
+        let mut bounds: Option<(usize, usize, usize, usize)> = None;
+        for (i, pixel) in self.frame().chunks_exact(4).enumerate() {
+            if pixel[..3] == backdrop {
+                continue;
+            }
+
+            let (x, y) = (i % width, i / width);
+            bounds = Some(match bounds {
+                None => (x, y, x, y),
+                Some((min_x, min_y, max_x, max_y)) => {
+                    (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+                }
+            });
+        }
+        bounds
+    }
+
+    /// Sets the pixel at (`x`, `y`) in the frame to `color`, so your cpu can draw graphics
+    /// directly without tiles or sprites. This works the same with and without a window.
+    ///
+    /// The PPU itself also draws every pixel of the screen once per frame, and the frame is
+    /// shown at the start of vblank. So a pixel set here is only visible if it is set after
+    /// the PPU drew that line, and it has to be set again every frame.
+    ///
+    /// Coordinates outside of the screen are ignored, just like with [`Ppu::blend_pixel`].
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        if self.resolution().contains(x, y) {
+            self.screen.draw_pixel(x, y, color);
+        }
+    }
+
+    /// Blends a color over the pixel at (`x`, `y`) in the frame, for example to draw a
+    /// translucent overlay from your cpu. Every channel becomes
+    /// `(color * alpha + old * (255 - alpha)) / 255`, so an `alpha` of 255 replaces the pixel.
+    /// Note that the PPU overwrites the pixel again when it is drawn in the next frame.
+    ///
+    /// Coordinates outside of the screen are ignored.
+    pub fn blend_pixel(&mut self, x: usize, y: usize, color: Color, alpha: u8) {
+        if self.resolution().contains(x, y) {
+            self.screen.blend_pixel(x, y, color, alpha);
+        }
+    }
+
+    /// Draws the outline of a `w` by `h` rectangle with its top left corner at (`x`, `y`),
+    /// clipped to the screen. Useful to show hitboxes while debugging collisions. Like
+    /// [`Ppu::blend_pixel`], the PPU draws over it again in the next frame.
+    pub fn draw_rect_outline(&mut self, x: usize, y: usize, w: usize, h: usize, color: Color) {
+        self.screen.draw_rect_outline(x, y, w, h, color);
+    }
+
+    /// A rough picture of the frame made of characters, `cols` wide and `rows` high, to look
+    /// at the output of a headless run in a terminal or test log. Every character stands for
+    /// the average brightness of a block of pixels, from a space for black to `@` for white.
+    /// Rows are separated by newlines.
+    pub fn ascii_preview(&self, cols: usize, rows: usize) -> String {
+        const RAMP: &[u8] = b" .:-=+*#%@";
+
+        // the pixels in block `i` out of `count`. every block is at least one pixel
+        let block = |i: usize, count: usize, size: usize| {
+            let start = (i * size / count).min(size - 1);
+            let end = ((i + 1) * size / count).clamp(start + 1, size);
+            start..end
+        };
+
+        let mut preview = String::with_capacity((cols + 1) * rows);
+        for row in 0..rows {
+            if row > 0 {
+                preview.push('\n');
+            }
+
+            let ys = block(row, rows, self.resolution().height as usize);
+            for col in 0..cols {
+                let xs = block(col, cols, self.resolution().width as usize);
+                let region = self.get_region(xs.start, ys.start, xs.len(), ys.len());
+
+                // perceived brightness, 0 to 255
+                let total: u32 = region
+                    .iter()
+                    .map(|&(r, g, b)| {
+                        (u32::from(r) * 299 + u32::from(g) * 587 + u32::from(b) * 114) / 1000
+                    })
+                    .sum();
+                let luminance = total / region.len() as u32;
+
+                preview.push(RAMP[luminance as usize * (RAMP.len() - 1) / 255] as char);
+            }
+        }
+        preview
+    }
+
+    /// Compares the current frame to a previous one (for example a copy of [`Ppu::frame`]
+    /// made one frame earlier) and produces an RGBA image of the same size in which changed
+    /// pixels are magenta and unchanged pixels are a dimmed version of the current frame.
+    ///
+    /// # Panics
+    /// When `previous` is not exactly as large as the current frame
+    pub fn frame_diff(&self, previous: &[u8]) -> Vec<u8> {
+        let current = self.frame();
+        assert_eq!(
+            previous.len(),
+            current.len(),
+            "previous frame has a different size than the current frame"
+        );
+
+        current
+            .chunks_exact(4)
+            .zip(previous.chunks_exact(4))
+            .flat_map(|(now, before)| {
+                if now == before {
+                    [now[0] / 4, now[1] / 4, now[2] / 4, 0xff]
+                } else {
+                    [0xff, 0x00, 0xff, 0xff]
+                }
+            })
+            .collect()
+    }
+
     /// For writes to 0x4014 (see NES docs at [https://www.nesdev.org/wiki/PPU_registers#OAMDMA](https://www.nesdev.org/wiki/PPU_registers#OAMDMA))
     ///
     /// There is no real DMA. When a value is written to 0x4014, you are supposed to pass the PPU
-    /// the right 256 bytes instantly, through this function.
+    /// the right 256 bytes instantly, through this function. The value written is the page to
+    /// copy from: writing `0x02` copies cpu memory `0x0200..=0x02FF` into OAM.
+    ///
+    /// On real hardware the cpu is stalled while the bytes are copied, for 513 cycles, or 514
+    /// when the write happened on an odd cpu cycle. Some games rely on this timing, so your cpu
+    /// should spend that many [ticks](Cpu::tick) doing nothing after a write to 0x4014.
+    ///
+    /// ```
+    /// # use tudelft_nes_ppu::{Mirroring, Ppu};
+    /// let mut ppu = Ppu::new(Mirroring::Horizontal);
+    /// let page: [u8; 256] = std::array::from_fn(|i| i as u8);
+    /// ppu.write_oam_dma(page);
+    /// assert_eq!(ppu.debug_snapshot().oam, page);
+    /// ```
     pub fn write_oam_dma(&mut self, data_to_write: [u8; 256]) {
         self.oam = data_to_write;
     }
 
-    fn update_scanline(&mut self, cpu: &mut impl Cpu, screen: &mut ScreenWriter) {
+    /// Whether the background or the sprites are shown. The PPU only fetches tiles and moves
+    /// the scroll position along while rendering is enabled.
+    fn rendering_enabled(&self) -> bool {
+        self.mask_register.show_background || self.mask_register.show_sprites
+    }
+
+    fn update_scanline(&mut self, cpu: &mut impl Cpu) {
         self.line_progress += 1;
+        self.dots += 1;
 
         if self.line_progress >= 257 && self.line_progress <= 320 {
             self.oam_addr.write(0);
         }
 
-        // Update x scroll every line
-        if self.line_progress == 256 {
-            self.controller_register.nametable_address &= !0x0400;
-            self.controller_register.nametable_address |= self.addr_new_nametable & 0x400;
-            self.scroll.x = self.scroll_access.x;
-        }
-
-        // Update y scroll every screen
-        if self.line_progress == 304 && self.scanline == 0 {
-            self.controller_register.nametable_address &= !0x0800;
-            self.controller_register.nametable_address |= self.addr_new_nametable & 0x800;
-            self.scroll.y = self.scroll_access.y;
+        // move the scroll position along like the real PPU does on the visible and pre-render lines
+        if self.rendering_enabled() && (self.scanline < 240 || self.scanline == 261) {
+            if self.line_progress == 256 {
+                self.scroll.increment_y();
+            }
+            if self.line_progress == 257 {
+                self.scroll.copy_horizontal();
+            }
+            if self.scanline == 261 && (280..=304).contains(&self.line_progress) {
+                self.scroll.copy_vertical();
+            }
         }
 
         if self.line_progress > 340 {
@@ -297,40 +1237,28 @@ impl Ppu {
 
             // read oam
             self.secondary_oam = [0xff; 32];
-            let mut sprite_index = 0;
-            for (index, sprite) in self
-                .oam
-                .iter()
-                .copied()
-                .skip(self.oam_addr.addr as usize)
-                .tuples::<(_, _, _, _)>()
-                .enumerate()
-            {
-                if self.scanline >= sprite.0 as usize
-                    && self.scanline
-                        < sprite.0 as usize + self.controller_register.sprite_size.1 as usize
-                {
-                    if sprite_index == 8 {
-                        self.status_register.sprite_overflow = true;
-                        break;
-                    }
-
-                    let b2 = sprite.2 & 0b1110_0011;
-
-                    self.secondary_oam[sprite_index * 4] = sprite.0;
-                    self.secondary_oam[sprite_index * 4 + 1] = sprite.1;
-                    // We set bit 2 of byte 2 whenever this sprite is sprite 0 (it has special behavior).
-                    // In the actual NES hardware this bit is *unused* so we *abuse* it
-                    self.secondary_oam[sprite_index * 4 + 2] =
-                        b2 | if index == 0 { 0b0000_0100 } else { 0 };
-                    self.secondary_oam[sprite_index * 4 + 3] = sprite.3;
-                    sprite_index += 1;
-                }
+            let (sprites, overflow) = self.evaluate_sprites(self.scanline);
+            if overflow {
+                self.status_register.sprite_overflow = true;
+            }
+            for (sprite_index, &oam_index) in sprites.iter().enumerate() {
+                let offset = oam_index as usize * 4;
+                let sprite = &self.oam[offset..offset + 4];
+
+                let b2 = sprite[2] & 0b1110_0011;
+
+                self.secondary_oam[sprite_index * 4] = sprite[0];
+                self.secondary_oam[sprite_index * 4 + 1] = sprite[1];
+                // We set bit 2 of byte 2 whenever this sprite is sprite 0 (it has special behavior).
+                // In the actual NES hardware this bit is *unused* so we *abuse* it
+                self.secondary_oam[sprite_index * 4 + 2] =
+                    b2 | if oam_index == 0 { 0b0000_0100 } else { 0 };
+                self.secondary_oam[sprite_index * 4 + 3] = sprite[3];
             }
 
             // we've just passed the 240th line, vblank begins!
             if self.scanline == 241 {
-                self.start_vblank(cpu, screen);
+                self.start_vblank(cpu);
             }
 
             if self.scanline > 261 {
@@ -340,20 +1268,94 @@ impl Ppu {
         }
     }
 
-    fn start_vblank(&mut self, cpu: &mut impl Cpu, screen: &mut ScreenWriter) {
+    /// Finds the sprites on `scanline`. Returns the OAM indices of the first 8 of them, and
+    /// whether there were more than that (a sprite overflow).
+    ///
+    /// `oam_addr` is always 0 when this happens during rendering, so evaluation starts at
+    /// the first sprite in OAM.
+    fn evaluate_sprites(&self, scanline: usize) -> (Vec<u8>, bool) {
+        let height = self.controller_register.sprite_size.1 as usize;
+        let mut on_line = (0..64u8).filter(|&index| {
+            let y = self.oam[index as usize * 4] as usize;
+            scanline >= y && scanline < y + height
+        });
+
+        let sprites = on_line.by_ref().take(8).collect();
+        let overflow = on_line.next().is_some();
+        (sprites, overflow)
+    }
+
+    /// How many times [`Cpu::tick`] was called during the last full frame, to check that the
+    /// cpu is clocked at the right rate. A frame is 341 * 262 dots, so with the default of 3
+    /// [dots per tick](Cpu::DOTS_PER_TICK) this is 29780 or 29781 for NTSC.
+    ///
+    /// Frames are counted from one vblank to the next. The PPU starts at the top of the
+    /// screen, so the first count after starting is lower. Only the run functions of this
+    /// crate count ticks.
+    pub fn ticks_last_frame(&self) -> usize {
+        self.ticks_last_frame
+    }
+
+    /// Whether sprite 0 hit the background in this frame, the same bit the cpu reads from the
+    /// status register (`0x2002`). A hit is an opaque pixel of sprite 0 drawn over an opaque
+    /// background pixel, while both the background and sprites are enabled in the mask
+    /// register. The rightmost column (x = 255) never hits.
+    ///
+    /// The flag is set during the dot that draws the overlapping pixel: the pixel at (x, y) is
+    /// dot x of scanline y, so the first [`Cpu::tick`] after it sees the flag. It stays set
+    /// through vblank, and is cleared at the end of vblank, when the PPU wraps around from the
+    /// pre-render scanline 261 to scanline 0.
+    pub fn sprite_zero_hit(&self) -> bool {
+        self.status_register.sprite_zero_hit
+    }
+
+    /// The scanline on which sprite 0 hit the background in the current frame, or `None` when it
+    /// didn't (yet). Games wait for this hit to time a split screen, so this shows exactly where
+    /// the split happens. The value stays readable during vblank, and is cleared when the next
+    /// frame starts.
+    pub fn sprite_zero_hit_scanline(&self) -> Option<u16> {
+        self.sprite_zero_hit_scanline
+    }
+
+    /// The indices (0 to 63) of the sprites in OAM that are drawn on `scanline`, in the order
+    /// they were found. Like on a real NES, at most 8 sprites fit on a line: any more are
+    /// dropped, and set the sprite overflow flag in the status register when the line is drawn.
+    /// This is why games with many sprites next to each other flicker.
+    ///
+    /// The evaluation is done again for the current OAM contents, so this also works for lines
+    /// which are not drawn yet.
+    pub fn sprites_on_scanline(&self, scanline: u16) -> Vec<u8> {
+        self.evaluate_sprites(scanline as usize).0
+    }
+
+    fn start_vblank(&mut self, cpu: &mut impl Cpu) {
+        self.frame_count += 1;
+        self.ticks_last_frame = std::mem::take(&mut self.ticks_this_frame);
+        if self.buttons.any_pressed() {
+            self.idle_frames = 0;
+        } else {
+            self.idle_frames += 1;
+        }
         self.status_register.vblank_started = true;
-        self.status_register.sprite_zero_hit = false;
-        self.status_register.sprite_overflow = false;
 
         if self.controller_register.should_generate_vblank_nmi {
+            self.nmi_pending = true;
+            self.nmi_count += 1;
             cpu.non_maskable_interrupt();
         }
 
-        screen.render_frame();
+        self.last_frame.copy_from_slice(self.screen.frame());
+        self.screen.render_frame();
     }
 
     fn end_vblank(&mut self) {
+        if let Some(color) = self.clear_color {
+            self.screen.clear(color);
+        }
         self.status_register.vblank_started = false;
+        self.status_register.sprite_zero_hit = false;
+        self.status_register.sprite_overflow = false;
+        self.sprite_zero_hit_scanline = None;
     }
 
     fn get_palette(&self, tile_x: usize, tile_y: usize, attr_table: u16) -> [Color; 4] {
@@ -370,22 +1372,19 @@ impl Ppu {
 
         let start = 1 + (palette_index as usize) * 4;
 
-        let mask = if self.mask_register.greyscale {
-            0x30
-        } else {
-            0xff
-        };
-
         [
-            NES_COLOR_PALLETE[(self.palette_table[0] & mask) as usize],
-            NES_COLOR_PALLETE[(self.palette_table[start] & mask) as usize],
-            NES_COLOR_PALLETE[(self.palette_table[start + 1] & mask) as usize],
-            NES_COLOR_PALLETE[(self.palette_table[start + 2] & mask) as usize],
+            self.palette_color(0),
+            self.palette_color(start),
+            self.palette_color(start + 1),
+            self.palette_color(start + 2),
         ]
     }
 
-    fn get_sprite_palette(&self, palette_index: u8) -> [Color; 4] {
-        let start = 0x11 + (palette_index * 4) as usize;
+    /// The color entry `slot` of the palette table refers to
+    fn palette_color(&self, slot: usize) -> Color {
+        if let Some(color) = self.forced_palette[slot] {
+            return color;
+        }
 
         let mask = if self.mask_register.greyscale {
             0x30
@@ -393,11 +1392,44 @@ impl Ppu {
             0xff
         };
 
+        self.palette[(self.palette_table[slot] & mask) as usize]
+    }
+
+    /// Every emphasis bit in the mask register darkens the *other* two color channels, like
+    /// on the real NES. Games set all three to fade the screen to black.
+    fn apply_emphasis(&self, color: Color) -> Color {
+        // how much a channel keeps of its brightness for every emphasis bit that darkens it
+        const ATTENUATION: f32 = 0.816;
+
+        let darken = |channel: u8, times: usize| {
+            (channel as f32 * ATTENUATION.powi(times as i32)).round() as u8
+        };
+        let (red, green, blue) = (
+            self.mask_register.emph_red,
+            self.mask_register.emph_green,
+            self.mask_register.emph_blue,
+        );
+
+        (
+            darken(color.0, green as usize + blue as usize),
+            darken(color.1, red as usize + blue as usize),
+            darken(color.2, red as usize + green as usize),
+        )
+    }
+
+    /// The color of the background where nothing is drawn, as it appears on screen
+    fn backdrop_color(&self) -> Color {
+        self.apply_emphasis(self.palette_color(0))
+    }
+
+    fn get_sprite_palette(&self, palette_index: u8) -> [Color; 4] {
+        let start = 0x11 + (palette_index * 4) as usize;
+
         [
-            NES_COLOR_PALLETE[0],
-            NES_COLOR_PALLETE[(self.palette_table[start] & mask) as usize],
-            NES_COLOR_PALLETE[(self.palette_table[start + 1] & mask) as usize],
-            NES_COLOR_PALLETE[(self.palette_table[start + 2] & mask) as usize],
+            self.palette[0],
+            self.palette_color(start),
+            self.palette_color(start + 1),
+            self.palette_color(start + 2),
         ]
     }
 
@@ -406,25 +1438,21 @@ impl Ppu {
     fn draw_pixel(
         &mut self,
         cpu: &mut impl Cpu,
-        screen: &mut ScreenWriter,
         x: usize,
         y: usize,
-        name_table_address: u16,
         ptr_x: i32, ptr_y: i32, //pixels pointed to by cursor
     ) -> bool {
-        let scroll_x = self.scroll.x;
-        let scroll_y = self.scroll.y;
-        let scrolled_x = (x as isize + scroll_x as isize).rem_euclid(WIDTH as isize * 2) as usize;
-        let scrolled_y = (y as isize + scroll_y as isize).rem_euclid(HEIGHT as isize * 2) as usize;
+        // the scroll position says which line of the nametables this is, and where it starts
+        let scrolled_x = (self.scroll.line_x() + x) % (WIDTH as usize * 2);
+        let (name_table_y, tile_y, tile_y_off) = self.scroll.line_y();
 
-        let name_table_idx = (scrolled_x / WIDTH as usize) + (scrolled_y / HEIGHT as usize) * 2;
+        let name_table_idx = (scrolled_x / WIDTH as usize) + name_table_y * 2;
         assert!(name_table_idx < 4);
 
-        let tile_nametable_address = name_table_address + (name_table_idx * 0x400) as u16;
+        let tile_nametable_address = 0x2000 + (name_table_idx * 0x400) as u16;
         let attr_table = tile_nametable_address + 0x3c0;
 
         let tile_x = (scrolled_x / 8) % 32;
-        let tile_y = (scrolled_y / 8) % 30;
 
         let off = tile_x + tile_y * 32;
 
@@ -433,7 +1461,6 @@ impl Ppu {
         let palette = self.get_palette(tile_x, tile_y, attr_table);
 
         let tile_x_off = 7 - (scrolled_x % 8);
-        let tile_y_off = scrolled_y % 8;
 
         let bank = self.controller_register.background_pattern_address;
 
@@ -450,28 +1477,33 @@ impl Ppu {
             (true, true) => palette[3],
         };
 
-        if self.mask_register.emph_red {
-            color.0 = 0xff;
-        }
-        if self.mask_register.emph_green {
-            color.1 = 0xff;
-        }
-        if self.mask_register.emph_blue {
-            color.2 = 0xff;
-        }
+        color = self.apply_emphasis(color);
 
         if x as i32 == ptr_x && y as i32 == ptr_y {//(x as i32 - px).abs() + (y as i32 - py).abs() < 2 {
-            if color.0>200 && color.1>200 && color.2>200 {
-                self.buttons.light = true;
-            } else {
-                self.buttons.light = false;
-            }
+            self.point_at(color);
         }
-        screen.draw_pixel(x, y, color);
+        self.screen.draw_pixel(x, y, color);
 
         bit_lower || bit_upper
     }
 
+    /// Called when `color` is drawn at the pixel the light gun points at
+    fn point_at(&mut self, color: Color) {
+        self.buttons.light = self.bright_enough(color);
+        self.pointed_color = Some(color);
+        self.pointed_drawn_at = self.dots;
+    }
+
+    /// The color of the debug checkerboard at a pixel, see [`Ppu::set_debug_layers`].
+    /// `cell_size` is the width and height of a single square in pixels.
+    fn debug_checkerboard(x: usize, y: usize, cell_size: usize) -> Color {
+        if (x / cell_size + y / cell_size) & 1 == 0 {
+            (0x30, 0x30, 0x30)
+        } else {
+            (0x50, 0x50, 0x50)
+        }
+    }
+
     #[inline]
     fn blanking(&self) -> bool {
         !(self.line_progress < 256 && self.scanline < 240)
@@ -481,14 +1513,12 @@ impl Ppu {
     fn draw_sprite_pixel(
         &mut self,
         cpu: &mut impl Cpu,
-        screen: &mut ScreenWriter,
         sprite: [u8; 4],
 
         x: usize,
         y: usize,
         mut sprite_x_off: u16,
         mut sprite_y_off: u16,
-        name_table: u16,
 
         ptr_x:i32,ptr_y:i32, //mouse pointer pixel coordinates
     ) -> bool {
@@ -540,37 +1570,30 @@ impl Ppu {
             sprite_zero_hit = true;
         }
 
-        if self.mask_register.emph_red {
-            color.0 = 0xff;
-        }
-        if self.mask_register.emph_green {
-            color.1 = 0xff;
-        }
-        if self.mask_register.emph_blue {
-            color.2 = 0xff;
+        color = self.apply_emphasis(color);
+
+        // sprites are disabled, show where they would have been drawn
+        if self.debug_layers && !self.mask_register.show_sprites {
+            color = Self::debug_checkerboard(x, y, 2);
         }
 
         let behind_background = sprite[2] & 0b0010_0000 > 0;
 
         // Don't draw a background sprite over background tiles,
         // but do draw it over the background color
-        if behind_background && self.draw_pixel(cpu, screen, x, y, name_table, ptr_x,ptr_y) {
+        if behind_background && self.draw_pixel(cpu, x, y, ptr_x,ptr_y) {
             return sprite_zero_hit;
         }
 
         if x as i32 == ptr_x && y as i32 == ptr_y {//(x as i32 - px).abs() + (y as i32 - py).abs() == 0 {
-            if color.0>200 && color.1>200 && color.2>200 {
-                self.buttons.light = true;
-            } else {
-                self.buttons.light = false;
-            }
+            self.point_at(color);
         }
-        screen.draw_pixel(x, y, color);
+        self.screen.draw_pixel(x, y, color);
 
         sprite_zero_hit
     }
 
-    fn draw_sprites(&mut self, cpu: &mut impl Cpu, screen: &mut ScreenWriter, name_table: u16, ptr_x:i32, ptr_y:i32) -> bool { //ptr_xy are mouse pointer coordinates
+    fn draw_sprites(&mut self, cpu: &mut impl Cpu, ptr_x:i32, ptr_y:i32) -> bool { //ptr_xy are mouse pointer coordinates
         let mut sprite_zero_hit = false;
 
         for i in (0..8).rev() {
@@ -585,13 +1608,11 @@ impl Ppu {
             {
                 sprite_zero_hit |= self.draw_sprite_pixel(
                     cpu,
-                    screen,
                     [sprite_y, sprite_1, sprite_2, sprite_x],
                     self.line_progress,
                     self.scanline,
                     (self.line_progress - sprite_x as usize) as u16,
                     (self.scanline - sprite_y as usize) as u16,
-                    name_table,
                     ptr_x,ptr_y,
                 );
             }
@@ -600,24 +1621,36 @@ impl Ppu {
         sprite_zero_hit
     }
 
-    /// the screen is optional, since sometimes there is no screen (headless mode)
-    pub(crate) fn update(&mut self, cpu: &mut impl Cpu, screen: &mut ScreenWriter, ptr_x:i32,ptr_y:i32) { //ptr_x/y are mouse pointer coords
-        self.update_scanline(cpu, screen);
+    pub(crate) fn update(&mut self, cpu: &mut impl Cpu, ptr_x:i32,ptr_y:i32) { //ptr_x/y are mouse pointer coords
+        self.update_scanline(cpu);
+
+        let pointer = (ptr_x >= 0 && ptr_y >= 0).then_some((ptr_x as usize, ptr_y as usize));
+        if pointer != self.pointer {
+            self.pointer = pointer;
+            self.pointed_color = None;
+        }
 
         if !self.blanking() {
-            let nametable_addr = self.controller_register.nametable_address;
-
-            self.draw_pixel(
-                cpu,
-                screen,
-                self.line_progress,
-                self.scanline,
-                nametable_addr,
-                ptr_x,ptr_y,
-            );
+            let background_opaque = if self.debug_layers && !self.mask_register.show_background {
+                let (x, y) = (self.line_progress, self.scanline);
+                self.screen
+                    .draw_pixel(x, y, Self::debug_checkerboard(x, y, 8));
+                false
+            } else {
+                self.draw_pixel(cpu, self.line_progress, self.scanline, ptr_x, ptr_y)
+            };
 
-            if self.draw_sprites(cpu, screen, nametable_addr, ptr_x,ptr_y) {
+            let sprite_zero_opaque = self.draw_sprites(cpu, ptr_x,ptr_y);
+            if sprite_zero_opaque
+                && background_opaque
+                && self.mask_register.show_background
+                && self.mask_register.show_sprites
+                && self.line_progress != 255
+            {
                 self.status_register.sprite_zero_hit = true;
+                if self.sprite_zero_hit_scanline.is_none() {
+                    self.sprite_zero_hit_scanline = Some(self.scanline as u16);
+                }
             }
         }
     }
@@ -19,8 +19,36 @@ pub enum PpuRegister {
     Data = 7,
 }
 
+impl PpuRegister {
+    /// The register a cpu address maps to. The 8 registers are at `0x2000..=0x2007`, and
+    /// mirrored every 8 bytes up to `0x3fff`. Any other address is not a PPU register.
+    ///
+    /// ```
+    /// # use tudelft_nes_ppu::PpuRegister;
+    /// assert_eq!(PpuRegister::from_address(0x2007), Some(PpuRegister::Data));
+    /// assert_eq!(PpuRegister::from_address(0x3456), Some(PpuRegister::Address));
+    /// assert_eq!(PpuRegister::from_address(0x4014), None);
+    /// ```
+    pub fn from_address(address: u16) -> Option<Self> {
+        if !(0x2000..=0x3fff).contains(&address) {
+            return None;
+        }
+
+        Some(match address & 0b111 {
+            0 => Self::Controller,
+            1 => Self::Mask,
+            2 => Self::Status,
+            3 => Self::OamAddress,
+            4 => Self::OamData,
+            5 => Self::Scroll,
+            6 => Self::Address,
+            7 => Self::Data,
+            _ => unreachable!(),
+        })
+    }
+}
+
 pub(crate) struct ControllerRegister {
-    pub(crate) nametable_address: u16,
     pub(crate) vram_increment: u16,
     pub(crate) sprite_pattern_address: u16,
     pub(crate) background_pattern_address: u16,
@@ -37,7 +65,6 @@ pub(crate) struct ControllerRegister {
 impl Default for ControllerRegister {
     fn default() -> Self {
         let mut s = Self {
-            nametable_address: 0x2000,
             vram_increment: 0,
             sprite_pattern_address: 0,
             background_pattern_address: 0,
@@ -52,6 +79,11 @@ impl Default for ControllerRegister {
 }
 
 impl ControllerRegister {
+    /// The value that was last written
+    pub fn value(&self) -> u8 {
+        self.binary_value
+    }
+
     pub fn write(&mut self, value: u8) {
         self.vram_increment = if (value & 0b0000_0100) > 0 { 32 } else { 1 };
         self.sprite_pattern_address = if (value & 0b0000_1000) > 0 {
@@ -109,6 +141,11 @@ impl Default for MaskRegister {
 }
 
 impl MaskRegister {
+    /// The value that was last written
+    pub fn value(&self) -> u8 {
+        self.binary_value
+    }
+
     pub fn write(&mut self, value: u8) {
         self.greyscale = (value & 0b0000_0001) > 0;
         self.show_bg_left = (value & 0b0000_0010) > 0;
@@ -132,18 +169,9 @@ pub(crate) struct StatusRegister {
 
 impl StatusRegister {
     pub fn read(&mut self) -> u8 {
-        let value = (self
-            .sprite_overflow
-            .then_some(0b0010_0000)
-            .unwrap_or_default())
-            | (self
-                .sprite_zero_hit
-                .then_some(0b0100_0000)
-                .unwrap_or_default())
-            | (self
-                .vblank_started
-                .then_some(0b1000_0000)
-                .unwrap_or_default());
+        let value = (if self.sprite_overflow { 0b0010_0000 } else { 0 })
+            | (if self.sprite_zero_hit { 0b0100_0000 } else { 0 })
+            | (if self.vblank_started { 0b1000_0000 } else { 0 });
 
         // SOMEHOW this is the expected behavior
         self.vblank_started = false;
@@ -184,18 +212,104 @@ impl OamAddrRegister {
     }
 }
 
+/// The internal scroll registers of the PPU, often called the "loopy" registers after the
+/// person who first documented them. `v` and `t` are laid out as `yyy NN YYYYY XXXXX`:
+/// fine y scroll, nametable select, coarse y scroll and coarse x scroll.
+///
+/// The write toggle shared by `0x2005` and `0x2006` (often called `w`) is kept by the
+/// [`Ppu`](super::Ppu), since the address register uses it too.
 #[derive(Default)]
 pub(crate) struct ScrollRegister {
+    /// The scroll position the background is drawn from
+    pub(crate) v: u16,
+    /// Where the cpu writes the scroll position to, copied to `v` while drawing
+    pub(crate) t: u16,
+    /// The fine x scroll, from 0 to 7
     pub(crate) x: u8,
-    pub(crate) y: u8,
 }
 
 impl ScrollRegister {
-    pub fn write(&mut self, value: u8, scroll_addr_latch: bool) {
-        if scroll_addr_latch {
-            self.y = value;
+    const COARSE_X: u16 = 0x001f;
+    const COARSE_Y: u16 = 0x03e0;
+    const NAMETABLE_X: u16 = 0x0400;
+    const NAMETABLE_Y: u16 = 0x0800;
+    const FINE_Y: u16 = 0x7000;
+    const HORIZONTAL: u16 = Self::COARSE_X | Self::NAMETABLE_X;
+
+    /// A write to the controller register (`0x2000`) selects the nametable
+    pub fn write_controller(&mut self, value: u8) {
+        self.t =
+            (self.t & !(Self::NAMETABLE_X | Self::NAMETABLE_Y)) | (u16::from(value & 0b11) << 10);
+    }
+
+    /// A write to the scroll register (`0x2005`), first x and then y
+    pub fn write_scroll(&mut self, value: u8, first_write: bool) {
+        if first_write {
+            self.t = (self.t & !Self::COARSE_X) | u16::from(value >> 3);
+            self.x = value & 0b111;
         } else {
-            self.x = value;
+            self.t = (self.t & !(Self::COARSE_Y | Self::FINE_Y))
+                | (u16::from(value & 0b1111_1000) << 2)
+                | (u16::from(value & 0b111) << 12);
         }
     }
+
+    /// A write to the address register (`0x2006`), which shares `t` with the scroll register.
+    /// The second write moves the new address to `v` right away, which games use to change
+    /// the scroll in the middle of a frame.
+    pub fn write_address(&mut self, value: u8, first_write: bool) {
+        if first_write {
+            self.t = (self.t & 0x00ff) | (u16::from(value & 0b0011_1111) << 8);
+        } else {
+            self.t = (self.t & 0xff00) | u16::from(value);
+            self.v = self.t;
+        }
+    }
+
+    /// Moves `v` one line down, wrapping around to the next nametable below after 30 rows
+    /// of tiles. Rows 30 and 31 are the attribute table, scrolling into them wraps without
+    /// changing nametables.
+    pub fn increment_y(&mut self) {
+        if self.v & Self::FINE_Y != Self::FINE_Y {
+            self.v += 1 << 12;
+            return;
+        }
+
+        self.v &= !Self::FINE_Y;
+        let coarse_y = match (self.v & Self::COARSE_Y) >> 5 {
+            29 => {
+                self.v ^= Self::NAMETABLE_Y;
+                0
+            }
+            31 => 0,
+            y => y + 1,
+        };
+        self.v = (self.v & !Self::COARSE_Y) | (coarse_y << 5);
+    }
+
+    /// Starts a new line at the x scroll position in `t`
+    pub fn copy_horizontal(&mut self) {
+        self.v = (self.v & !Self::HORIZONTAL) | (self.t & Self::HORIZONTAL);
+    }
+
+    /// Starts a new frame at the y scroll position in `t`
+    pub fn copy_vertical(&mut self) {
+        self.v = (self.v & Self::HORIZONTAL) | (self.t & !Self::HORIZONTAL);
+    }
+
+    /// The x position in the 512 pixels wide area of the four nametables where this line
+    /// starts
+    pub fn line_x(&self) -> usize {
+        let nametable = usize::from(self.v & Self::NAMETABLE_X != 0);
+        nametable * 256 + (self.v & Self::COARSE_X) as usize * 8 + self.x as usize
+    }
+
+    /// The nametable below (0 or 1), coarse y and fine y of the line that is drawn
+    pub fn line_y(&self) -> (usize, usize, usize) {
+        (
+            usize::from(self.v & Self::NAMETABLE_Y != 0),
+            ((self.v & Self::COARSE_Y) >> 5) as usize,
+            ((self.v & Self::FINE_Y) >> 12) as usize,
+        )
+    }
 }
@@ -0,0 +1,70 @@
+use crate::ppu::Ppu;
+
+/// A copy of the internals of the PPU at one moment, made by [`Ppu::debug_snapshot`].
+///
+/// Unlike [`Ppu::save_state`], this is meant to be looked at, for example by a debugger that
+/// shows the nametables and sprites. Since it's a copy, it can be sent to another thread and
+/// looked at there while the emulation continues, without ever locking the PPU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PpuSnapshot {
+    /// The scanline that is being drawn, see [`Ppu::scanline`]
+    pub scanline: u16,
+    /// The dot (cycle) on that scanline, see [`Ppu::dot`]
+    pub dot: u16,
+    /// See [`Ppu::frame_count`]
+    pub frame_count: u64,
+
+    /// The value last written to the controller register (`0x2000`)
+    pub controller: u8,
+    /// The value last written to the mask register (`0x2001`)
+    pub mask: u8,
+    /// The flags of the status register (`0x2002`). Making the snapshot doesn't clear them,
+    /// like a read by the cpu would.
+    pub status: u8,
+    /// The address the cpu reads and writes vram at through `0x2007`
+    pub address: u16,
+    /// The current scroll position (the internal `v` register)
+    pub scroll_v: u16,
+    /// The scroll position written by the cpu (the internal `t` register)
+    pub scroll_t: u16,
+    /// The fine x scroll, from 0 to 7
+    pub fine_x: u8,
+    /// The address in OAM that `0x2004` reads and writes
+    pub oam_address: u8,
+
+    /// The nametables and attribute tables. See [`Ppu`](crate::Ppu) for when the upper half is
+    /// used.
+    pub vram: [u8; 4096],
+    /// All 64 sprites, 4 bytes each
+    pub oam: [u8; 256],
+    /// The palette table at `0x3F00..=0x3F1F`
+    pub palette_table: [u8; 32],
+}
+
+impl Ppu {
+    /// Copies everything a debugger may want to show, see [`PpuSnapshot`]. To look at it from
+    /// another thread, send it over a channel, for example every frame from the hook of
+    /// [`run_cpu_with_hook`](crate::run_cpu_with_hook).
+    pub fn debug_snapshot(&self) -> PpuSnapshot {
+        let status = (u8::from(self.status_register.sprite_overflow) << 5)
+            | (u8::from(self.status_register.sprite_zero_hit) << 6)
+            | (u8::from(self.status_register.vblank_started) << 7);
+
+        PpuSnapshot {
+            scanline: self.scanline as u16,
+            dot: self.line_progress as u16,
+            frame_count: self.frame_count,
+            controller: self.controller_register.value(),
+            mask: self.mask_register.value(),
+            status,
+            address: self.addr.addr,
+            scroll_v: self.scroll.v,
+            scroll_t: self.scroll.t,
+            fine_x: self.scroll.x,
+            oam_address: self.oam_addr.addr,
+            vram: self.vram,
+            oam: self.oam,
+            palette_table: self.palette_table,
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use crate::ppu::Ppu;
+use crate::{ButtonName, Mirroring};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// The first bytes of every save state
+const MAGIC: &[u8; 4] = b"NPPU";
+/// Changes whenever the layout of a save state changes, so old save states fail to load
+/// instead of loading garbage
+const VERSION: u16 = 1;
+
+/// Why a save state couldn't be loaded by [`Ppu::load_state`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    /// The data doesn't start like a save state of the PPU
+    BadMagic,
+    /// The save state was made by a different version of this library
+    UnsupportedVersion(u16),
+    /// The save state ends before all of the state was read
+    Truncated,
+    /// The save state contains a value the PPU can't have
+    Invalid(&'static str),
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a save state of the ppu"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "save state has version {version}, only version {VERSION} is supported"
+            ),
+            Self::Truncated => write!(f, "save state is truncated"),
+            Self::Invalid(what) => write!(f, "save state contains an invalid {what}"),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.bytes.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("boolean")),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+}
+
+fn mirroring_to_byte(mirroring: Mirroring) -> u8 {
+    match mirroring {
+        Mirroring::Horizontal => 0,
+        Mirroring::Vertical => 1,
+        Mirroring::FourScreen => 2,
+        Mirroring::SingleScreenLower => 3,
+        Mirroring::SingleScreenUpper => 4,
+    }
+}
+
+fn mirroring_from_byte(byte: u8) -> Result<Mirroring, StateError> {
+    Ok(match byte {
+        0 => Mirroring::Horizontal,
+        1 => Mirroring::Vertical,
+        2 => Mirroring::FourScreen,
+        3 => Mirroring::SingleScreenLower,
+        4 => Mirroring::SingleScreenUpper,
+        _ => return Err(StateError::Invalid("mirroring mode")),
+    })
+}
+
+impl Ppu {
+    /// Takes a snapshot of everything the PPU will need to continue from this exact point:
+    /// its registers, the scroll position and the write toggle, vram, oam, the palette table,
+    /// the position in the frame and the buttons that are pressed. Load it again with
+    /// [`Ppu::load_state`]. Together with a snapshot of your cpu, this makes save states or
+    /// rewinding possible.
+    ///
+    /// A save state is about 4.5KB. The pixels drawn so far are not part of it, the next
+    /// frame draws over them anyway. Neither is anything that is not part of the emulated
+    /// hardware, like the color palette and the debug settings, and neither is the
+    /// [`Apu`](crate::Apu): after loading, the sound just continues.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(4608);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+
+        out.push(mirroring_to_byte(self.mirroring));
+        out.extend_from_slice(&(self.scanline as u16).to_le_bytes());
+        out.extend_from_slice(&(self.line_progress as u16).to_le_bytes());
+        out.extend_from_slice(&self.frame_count.to_le_bytes());
+        out.extend_from_slice(&self.dots.to_le_bytes());
+        out.extend_from_slice(&(self.ticks_this_frame as u64).to_le_bytes());
+        out.extend_from_slice(&(self.ticks_last_frame as u64).to_le_bytes());
+
+        out.push(self.controller_register.value());
+        out.push(self.mask_register.value());
+        out.push(u8::from(self.status_register.sprite_overflow));
+        out.push(u8::from(self.status_register.sprite_zero_hit));
+        out.push(u8::from(self.status_register.vblank_started));
+        out.extend_from_slice(&self.addr.addr.to_le_bytes());
+        out.extend_from_slice(&self.scroll.v.to_le_bytes());
+        out.extend_from_slice(&self.scroll.t.to_le_bytes());
+        out.push(self.scroll.x);
+        out.push(self.oam_addr.addr);
+        out.push(u8::from(self.scroll_addr_latch));
+        out.push(self.bus);
+        out.push(self.data_buffer);
+        out.push(u8::from(self.nmi_pending));
+        out.extend_from_slice(
+            &self
+                .sprite_zero_hit_scanline
+                .unwrap_or(u16::MAX)
+                .to_le_bytes(),
+        );
+
+        out.extend_from_slice(&self.palette_table);
+        out.extend_from_slice(&self.vram);
+        out.extend_from_slice(&self.oam);
+        out.extend_from_slice(&self.secondary_oam);
+
+        out.extend_from_slice(&self.buttons.to_bits().to_le_bytes());
+        out.push(u8::from(self.buttons.light));
+        out.push(u8::from(self.buttons.trigger));
+        out.push(u8::from(self.controller_strobe));
+        out.extend_from_slice(&self.controller_reads);
+        out.push(u8::from(self.paused));
+
+        out
+    }
+
+    /// Continues from a snapshot made by [`Ppu::save_state`]. When the snapshot can't be
+    /// loaded, the PPU is left as it was.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut r = Reader { bytes: state };
+        if r.take(MAGIC.len()).map_err(|_| StateError::BadMagic)? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = r.u16()?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        // read everything before changing anything, so a bad state leaves the PPU untouched
+        let mirroring = mirroring_from_byte(r.u8()?)?;
+        let scanline = r.u16()? as usize;
+        let line_progress = r.u16()? as usize;
+        let frame_count = r.u64()?;
+        let dots = r.u64()?;
+        let ticks_this_frame = r.u64()? as usize;
+        let ticks_last_frame = r.u64()? as usize;
+        if scanline > 261 || line_progress > 340 {
+            return Err(StateError::Invalid("position in the frame"));
+        }
+
+        let controller = r.u8()?;
+        let mask = r.u8()?;
+        let sprite_overflow = r.bool()?;
+        let sprite_zero_hit = r.bool()?;
+        let vblank_started = r.bool()?;
+        let addr = r.u16()?;
+        let v = r.u16()?;
+        let t = r.u16()?;
+        let x = r.u8()?;
+        let oam_addr = r.u8()?;
+        let scroll_addr_latch = r.bool()?;
+        let bus = r.u8()?;
+        let data_buffer = r.u8()?;
+        let nmi_pending = r.bool()?;
+        let sprite_zero_hit_scanline = Some(r.u16()?).filter(|&line| line != u16::MAX);
+
+        let palette_table = r.array::<32>()?;
+        let vram = r.array::<4096>()?;
+        let oam = r.array::<256>()?;
+        let secondary_oam = r.array::<32>()?;
+
+        let buttons = r.u16()?;
+        let light = r.bool()?;
+        let trigger = r.bool()?;
+        let controller_strobe = r.bool()?;
+        let controller_reads = r.array::<2>()?;
+        let paused = r.bool()?;
+        if !r.bytes.is_empty() {
+            return Err(StateError::Invalid("length"));
+        }
+
+        self.mirroring = mirroring;
+        self.scanline = scanline;
+        self.line_progress = line_progress;
+        self.frame_count = frame_count;
+        self.dots = dots;
+        // what the light gun saw was drawn before the state was loaded
+        self.pointed_color = None;
+        self.pointed_drawn_at = 0;
+        self.ticks_this_frame = ticks_this_frame;
+        self.ticks_last_frame = ticks_last_frame;
+
+        self.controller_register.write(controller);
+        self.mask_register.write(mask);
+        self.status_register.sprite_overflow = sprite_overflow;
+        self.status_register.sprite_zero_hit = sprite_zero_hit;
+        self.status_register.vblank_started = vblank_started;
+        self.addr.addr = addr;
+        self.scroll.v = v;
+        self.scroll.t = t;
+        self.scroll.x = x;
+        self.oam_addr.addr = oam_addr;
+        self.scroll_addr_latch = scroll_addr_latch;
+        self.bus = bus;
+        self.data_buffer = data_buffer;
+        self.nmi_pending = nmi_pending;
+        self.sprite_zero_hit_scanline = sprite_zero_hit_scanline;
+
+        self.palette_table = palette_table;
+        self.vram = vram;
+        self.oam = oam;
+        self.secondary_oam = secondary_oam;
+
+        for idx in 0..16 {
+            if let Some(name) = ButtonName::from_index(idx) {
+                self.buttons.set(name, buttons & 1 << idx != 0);
+            }
+        }
+        self.buttons.light = light;
+        self.buttons.trigger = trigger;
+        self.controller_strobe = controller_strobe;
+        self.controller_reads = controller_reads;
+        self.paused = paused;
+
+        Ok(())
+    }
+}
+
+/// Serializes the PPU as the bytes of [`Ppu::save_state`]
+#[cfg(feature = "serde")]
+impl serde::Serialize for Ppu {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.save_state())
+    }
+}
+
+/// Deserializes a PPU from the bytes of [`Ppu::save_state`]. Everything that is not part of
+/// a save state is set up like [`Ppu::new`] does.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Ppu {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StateVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for StateVisitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+                write!(f, "the bytes of a ppu save state")
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+                Ok(bytes.to_vec())
+            }
+
+            // formats without a byte type, like json, store the bytes as a list of numbers
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(bytes)
+            }
+        }
+
+        let state = deserializer.deserialize_bytes(StateVisitor)?;
+        let mut ppu = Ppu::new(Mirroring::Horizontal);
+        ppu.load_state(&state).map_err(serde::de::Error::custom)?;
+        Ok(ppu)
+    }
+}
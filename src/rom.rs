@@ -0,0 +1,166 @@
+use crate::Mirroring;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// The size of the header at the start of every iNES file
+const HEADER_SIZE: usize = 16;
+/// A trainer is 512 bytes of code that some dumps have between the header and the prg rom
+const TRAINER_SIZE: usize = 512;
+const PRG_ROM_BANK_SIZE: usize = 16 * 1024;
+const CHR_ROM_BANK_SIZE: usize = 8 * 1024;
+
+/// Why an iNES file couldn't be loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomError {
+    /// The file doesn't start with `NES` followed by `0x1A`, so it's not an iNES file
+    BadMagic,
+    /// The file is shorter than its header says it is
+    Truncated {
+        /// How many bytes the header says the file has
+        expected: usize,
+        /// How many bytes the file actually has
+        actual: usize,
+    },
+    /// The rom uses a mapper that is not in the list given to [`Rom::require_mapper`]
+    UnsupportedMapper(u16),
+}
+
+impl Display for RomError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not an iNES file"),
+            Self::Truncated { expected, actual } => {
+                write!(
+                    f,
+                    "rom is truncated: expected {expected} bytes, got {actual}"
+                )
+            }
+            Self::UnsupportedMapper(mapper) => write!(f, "mapper {mapper} is not supported"),
+        }
+    }
+}
+
+impl Error for RomError {}
+
+/// A game, loaded from an iNES (`.nes`) file.
+///
+/// The PPU doesn't read the pattern tables itself: it asks your cpu through
+/// [`Cpu::ppu_read_chr_rom`](crate::Cpu::ppu_read_chr_rom), since which bytes it gets
+/// depends on the mapper. For mapper 0 that can simply return
+/// `rom.chr_rom()[offset as usize]`.
+///
+/// ```
+/// # use tudelft_nes_ppu::{Mirroring, Ppu, Rom};
+/// // usually this comes from `std::fs::read("game.nes")`
+/// let mut bytes = vec![b'N', b'E', b'S', 0x1a, 1, 1, 0b0000_0001, 0];
+/// bytes.resize(16 + 16 * 1024 + 8 * 1024, 0);
+///
+/// let rom = Rom::from_ines_bytes(&bytes)?.require_mapper(&[0])?;
+/// assert_eq!(rom.mirroring(), Mirroring::Vertical);
+/// assert_eq!(rom.prg_rom().len(), 16 * 1024);
+///
+/// let ppu = Ppu::new(rom.mirroring());
+/// # Ok::<(), tudelft_nes_ppu::RomError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Rom {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    mapper: u16,
+    mirroring: Mirroring,
+    battery: bool,
+}
+
+impl Rom {
+    /// Parses the contents of an iNES file. Files in the NES 2.0 format are read like
+    /// iNES files, except that they can give the mapper number in 12 bits instead of 8.
+    /// A trainer, if there is one, is skipped.
+    pub fn from_ines_bytes(bytes: &[u8]) -> Result<Self, RomError> {
+        if bytes.len() < 4 || bytes[0..4] != *b"NES\x1a" {
+            return Err(RomError::BadMagic);
+        }
+        if bytes.len() < HEADER_SIZE {
+            return Err(RomError::Truncated {
+                expected: HEADER_SIZE,
+                actual: bytes.len(),
+            });
+        }
+
+        let prg_size = bytes[4] as usize * PRG_ROM_BANK_SIZE;
+        let chr_size = bytes[5] as usize * CHR_ROM_BANK_SIZE;
+        let flags6 = bytes[6];
+        let flags7 = bytes[7];
+
+        let nes2 = flags7 & 0b0000_1100 == 0b0000_1000;
+        let mut mapper = (flags7 & 0xf0) as u16 | (flags6 >> 4) as u16;
+        if nes2 {
+            mapper |= ((bytes[8] & 0x0f) as u16) << 8;
+        }
+
+        let mirroring = if flags6 & 0b0000_1000 != 0 {
+            Mirroring::FourScreen
+        } else if flags6 & 0b0000_0001 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        let prg_start = if flags6 & 0b0000_0100 != 0 {
+            HEADER_SIZE + TRAINER_SIZE
+        } else {
+            HEADER_SIZE
+        };
+        let chr_start = prg_start + prg_size;
+        let end = chr_start + chr_size;
+        if bytes.len() < end {
+            return Err(RomError::Truncated {
+                expected: end,
+                actual: bytes.len(),
+            });
+        }
+
+        Ok(Self {
+            prg_rom: bytes[prg_start..chr_start].to_vec(),
+            chr_rom: bytes[chr_start..end].to_vec(),
+            mapper,
+            mirroring,
+            battery: flags6 & 0b0000_0010 != 0,
+        })
+    }
+
+    /// Checks that the rom uses one of the mappers your emulator implements, so games that
+    /// wouldn't work give a clear error instead of garbage on screen.
+    pub fn require_mapper(self, supported: &[u16]) -> Result<Self, RomError> {
+        if supported.contains(&self.mapper) {
+            Ok(self)
+        } else {
+            Err(RomError::UnsupportedMapper(self.mapper))
+        }
+    }
+
+    /// The program code, a multiple of 16KB
+    pub fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    /// The graphics, a multiple of 8KB. This is empty when the cartridge has character
+    /// RAM instead, see [`Cpu::ppu_memory_write`](crate::Cpu::ppu_memory_write).
+    pub fn chr_rom(&self) -> &[u8] {
+        &self.chr_rom
+    }
+
+    /// The number of the mapper on the cartridge
+    pub fn mapper(&self) -> u16 {
+        self.mapper
+    }
+
+    /// The mirroring mode to create the [`Ppu`](crate::Ppu) with
+    pub fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    /// Whether the cartridge has battery backed memory at `0x6000..=0x7FFF`, to keep save games
+    pub fn has_battery(&self) -> bool {
+        self.battery
+    }
+}
@@ -1,134 +1,358 @@
+#[cfg(feature = "display")]
+use crate::config::{PlayerInput, PresentMode, TriggerButton};
+use crate::config::{Pacing, RunConfig};
 use crate::cpu::Cpu;
-use crate::screen::{ButtonName, Message, Screen, ScreenWriter, ScreenReader};
-use crate::{Mirroring, Ppu, CPU_FREQ, HEIGHT, WIDTH};
-use pixels::{Pixels, SurfaceTexture};
-use winit::dpi::PhysicalSize;
+#[cfg(feature = "display")]
+use crate::screen::Screen;
+use crate::screen::{Backend, ButtonName, Buttons, Message, Resolution, ScreenWriter};
+use crate::stepper::Stepper;
+#[cfg(feature = "display")]
+use crate::KeyMap;
+use crate::{Mirroring, Ppu};
+#[cfg(feature = "display")]
+use pixels::{PixelsBuilder, SurfaceTexture};
+#[cfg(feature = "display")]
+use std::any::Any;
+use std::collections::VecDeque;
+#[cfg(feature = "display")]
+use std::env;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "display")]
+use std::fs::File;
+use std::io;
+#[cfg(feature = "display")]
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+#[cfg(feature = "display")]
 use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::{Duration, Instant};
-use std::{env, thread};
-use winit::event::{ElementState, Event, VirtualKeyCode, WindowEvent, MouseButton};
+#[cfg(feature = "display")]
+use winit::dpi::PhysicalSize;
+#[cfg(feature = "display")]
+use winit::event::{ElementState, Event, MouseButton, WindowEvent};
+#[cfg(feature = "display")]
 use winit::event_loop::{ControlFlow, EventLoop};
+#[cfg(feature = "display")]
 use winit::window::WindowBuilder;
 
+/// Timing information about the emulation, see [`RunConfig::stats`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EmulationStats {
+    /// How long a frame should take when emulating at full speed
+    pub target_frame_time: Duration,
+    /// How long the last frame(s) actually took
+    pub actual_frame_time: Duration,
+    /// `target_frame_time / actual_frame_time` as a percentage. 100% is full speed,
+    /// lower means the emulation can't keep up.
+    pub speed_percent: f64,
+    /// Frames per second, averaged over roughly the last second so it doesn't jump around
+    /// when shown on screen
+    pub fps: f64,
+    /// How far the emulation is behind real time, or zero when it keeps up. When this keeps
+    /// growing, the computer is too slow to emulate at full speed.
+    pub behind: Duration,
+}
+
+/// What happened during a run of [`run_cpu_headless_stats`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct RunStats {
+    /// How many times [`Cpu::tick`] was called
+    pub cycles: usize,
+    /// How many frames were finished, which is how many times vblank started
+    pub frames: usize,
+    /// How many of those vblanks gave the cpu an NMI. This is lower than `frames` when the
+    /// game turned NMIs off for a while, for example while loading a level.
+    pub nmis: usize,
+}
+
+/// Sleeps until `duration` has passed, but busy-waits for the last millisecond since
+/// sleeping is not precise on every platform.
+fn sleep_spin(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    if let Some(sleep_time) = duration.checked_sub(Duration::from_millis(1)) {
+        thread::sleep(sleep_time);
+    }
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
+
+/// Converts a position in a window of `window_size` (width, height) to the coordinates of the
+/// NES pixel shown there, assuming the frame is stretched over the whole window like
+/// [`run_cpu`](crate::run_cpu) does without [`RunConfig::integer_scaling`]. Returns `None` when the position is outside of the window.
+///
+/// ```
+/// # use tudelft_nes_ppu::window_to_nes;
+/// assert_eq!(window_to_nes((0.0, 0.0), (512, 480)), Some((0, 0)));
+/// assert_eq!(window_to_nes((256.0, 240.0), (512, 480)), Some((128, 120)));
+/// assert_eq!(window_to_nes((511.9, 479.9), (512, 480)), Some((255, 239)));
+/// assert_eq!(window_to_nes((512.0, 100.0), (512, 480)), None);
+/// ```
+pub fn window_to_nes(pos: (f64, f64), window_size: (u32, u32)) -> Option<(u16, u16)> {
+    window_to_frame(pos, window_size, Resolution::NES)
+}
+
+/// Like [`window_to_nes`], for a frame of any [`Resolution`].
+pub(crate) fn window_to_frame(
+    pos: (f64, f64),
+    window_size: (u32, u32),
+    resolution: Resolution,
+) -> Option<(u16, u16)> {
+    let (width, height) = window_size;
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let (relx, rely) = (pos.0 / width as f64, pos.1 / height as f64);
+    if !(0.0..1.0).contains(&relx) || !(0.0..1.0).contains(&rely) {
+        return None;
+    }
+
+    // the min guards against rounding up at the very edge
+    let x = ((resolution.width as f64 * relx) as u16).min(resolution.width as u16 - 1);
+    let y = ((resolution.height as f64 * rely) as u16).min(resolution.height as u16 - 1);
+    Some((x, y))
+}
+
+/// Converts a position in the window to the coordinates of the pixel on the NES screen.
+fn pointed_pixel(screen: &ScreenWriter, posx: f64, posy: f64) -> Option<(i32, i32)> {
+    let (x, y) = screen.window_to_nes((posx, posy))?;
+    Some((i32::from(x), i32::from(y)))
+}
+
+/// Waits for the next message while the emulation is paused, see [`RunConfig::mute_on_pause`].
+/// When the sound keeps playing, the sound chip runs on its own in the meantime.
+fn recv_paused(ppu: &mut Ppu, mute: bool, cpu_freq: f64) -> Option<Message> {
+    if mute || ppu.apu.sink.is_none() {
+        ppu.apu.mute();
+        return ppu.screen.recv();
+    }
+
+    let mut last_tick = Instant::now();
+    loop {
+        if let Some(msg) = ppu.screen.try_recv() {
+            return Some(msg);
+        }
+        std::thread::sleep(Duration::from_millis(1));
+
+        let cycles = (last_tick.elapsed().as_secs_f64() * cpu_freq) as u64;
+        last_tick += Duration::from_secs_f64(cycles as f64 / cpu_freq);
+        for _ in 0..cycles {
+            ppu.apu.tick();
+        }
+    }
+}
+
 fn run_ppu<CPU: Cpu>(
-    mirroring: Mirroring,
     cpu: &mut CPU,
-    writer: &mut ScreenWriter,
+    ppu: Ppu,
+    config: &RunConfig,
     max_cycles: Option<usize>,
+    mut on_frame: impl FnMut(&Ppu),
+    mut stop: impl FnMut(&CPU, &Ppu) -> bool,
 ) -> Result<(), CPU::TickError> {
-    const ITER_PER_CYCLE: usize = 1000;
-    let mut ppu = Ppu::new(mirroring);
+    let batch_cycles = config.batch_cycles;
+    assert!(batch_cycles > 0, "a batch has to be at least 1 cycle");
+    let mut stepper = Stepper::with_ppu(ppu);
+
+    assert!(config.speed > 0.0, "emulation speed has to be more than 0");
+    let mut speed = config.speed;
+    assert!(config.cpu_freq > 0.0, "cpu frequency has to be more than 0");
+    let cpu_freq = config.cpu_freq;
+    stepper.ppu.apu.cpu_freq = cpu_freq;
+    assert!(CPU::DOTS_PER_TICK > 0, "a tick has to move the PPU along");
+    // how many real cpu cycles one tick stands for: the PPU always runs 3 dots per cpu cycle
+    let cycles_per_tick = CPU::DOTS_PER_TICK as f64 / 3.0;
 
     let mut busy_time = Duration::default();
     let mut cycles = 0;
+    // ticks since the speed last changed, to know how long they should have taken
+    let mut paced_cycles = 0;
     let mut last_tick = Instant::now();
+    let mut last_behind_warning = None;
+    // how many times in a row sleeping took much longer than asked for
+    let mut oversleeps = 0;
+    let mut warned_oversleep = false;
 
-    let mut mouse_x = 0;
-    let mut mouse_y = 0;
-
+    // a frame is 262 lines of 341 dots
+    let target_frame_time = Duration::from_secs_f64(341.0 * 262.0 / 3.0 / cpu_freq);
+    // frames drawn since the stats were last sent. The frame count can't be used for this,
+    // since rewinding makes it go back
+    let mut frames_since_stats: u32 = 0;
+    let mut last_frame_time = Instant::now();
+    let mut average_frame_time = target_frame_time.as_secs_f64();
+    let mut behind = Duration::default();
+    // button changes waiting for the next frame, see `RunConfig::buttons_per_frame`
+    let mut pending_buttons = Vec::new();
+    // snapshots of the cpu and the PPU at the start of the last frames, newest at the back
+    let mut rewind_buffer: VecDeque<(Vec<u8>, Vec<u8>)> = VecDeque::new();
+    let rewind_frames = config.rewind_seconds as usize * 60;
+    let mut rewinding = false;
+    // how many frames every turbo button stays pressed and then released, and the frame it
+    // was pressed at while it's held
+    let turbo_frames: Vec<u64> = config
+        .turbo
+        .iter()
+        .map(|&(_, rate)| {
+            assert!(
+                rate > 0.0,
+                "turbo buttons have to fire more than 0 times per second"
+            );
+            (60.0 / rate / 2.0).round().max(1.0) as u64
+        })
+        .collect();
+    let mut turbo_held: Vec<Option<u64>> = vec![None; config.turbo.len()];
+    let mut frames: u64 = 0;
 
     loop {
-        for _ in 0..ITER_PER_CYCLE {
-            if let ScreenWriter::Real {
-                control_rx: buttons_rx,
-                screen,
-                ..
-            } = writer
-            {
-                while let Ok(msg) = buttons_rx.try_recv() {
-                    match msg {
-                        Message::Button(name, pressed) => match name {
-                            ButtonName::A1 => {
-                                ppu.buttons.a1 = pressed;
-                            }
-                            ButtonName::B1 => {
-                                ppu.buttons.b1 = pressed;
-                            }
-                            ButtonName::Up1 => {
-                                ppu.buttons.up1 = pressed;
-                            }
-                            ButtonName::Down1 => {
-                                ppu.buttons.down1 = pressed;
-                            }
-                            ButtonName::Left1 => {
-                                ppu.buttons.left1 = pressed;
-                            }
-                            ButtonName::Right1 => {
-                                ppu.buttons.right1 = pressed;
-                            }
-                            ButtonName::Start1 => {
-                                ppu.buttons.start1 = pressed;
-                            }
-                            ButtonName::Select1 => {
-                                ppu.buttons.select1 = pressed;
-                            }
-                            ButtonName::A2 => {
-                                ppu.buttons.a2 = pressed;
-                            }
-                            ButtonName::B2 => {
-                                ppu.buttons.b2 = pressed;
-                            }
-                            ButtonName::Up2 => {
-                                ppu.buttons.up2 = pressed;
-                            }
-                            ButtonName::Down2 => {
-                                ppu.buttons.down2 = pressed;
-                            }
-                            ButtonName::Left2 => {
-                                ppu.buttons.left2 = pressed;
-                            }
-                            ButtonName::Right2 => {
-                                ppu.buttons.right2 = pressed;
-                            }
-                            ButtonName::Start2 => {
-                                ppu.buttons.start2 = pressed;
-                            }
-                            ButtonName::Select2 => {
-                                ppu.buttons.select2 = pressed;
-                            }
-                            ButtonName::MouseLeft => {
-                                ppu.buttons.trigger = pressed;
-                            }
-                        },
-                        Message::Pause(true) => {
-                            while let Message::Pause(true) =
-                                buttons_rx.recv().expect("sender closed")
-                            {
-                            }
-                            // skip over previous iterations
-                            last_tick = Instant::now();
+        for _ in 0..batch_cycles {
+            while let Some(msg) = stepper.ppu.screen.try_recv() {
+                if let Message::Button(name, pressed) = msg {
+                    for (idx, &(turbo, _)) in config.turbo.iter().enumerate() {
+                        if turbo == name {
+                            turbo_held[idx] = pressed.then_some(frames);
                         }
-                        Message::Pause(false) => {}
-                        Message::PixelPointed(posx,posy) => {
-                            let reader = screen.0.as_ref();
-                            if let ScreenReader::Real{window, ..}= reader {
-                                //0: take the position
-                                //1: take the screen size
-                                let screensize = window.inner_size();
-                                //2: compute relative screen dimensions
-                                let (relx,rely) = (posx / screensize.width as f64, posy / screensize.height as f64);
-                                //3: compute pointed pixel coordinates
-                                (mouse_x,mouse_y) = ((WIDTH as f64 * relx) as i32,(HEIGHT as f64 * rely) as i32);
-                                //mouse_x=mouse_x.min(WIDTH as i32-1).max(0); //bounding not required because we are no longer indexing pixels
-                                //mouse_y=mouse_y.min(HEIGHT as i32-1).max(0);
+                    }
+                }
+
+                match msg {
+                    Message::Button(name, pressed) if config.buttons_per_frame => {
+                        pending_buttons.push((name, pressed));
+                    }
+                    Message::Button(name, pressed) => stepper.ppu.handle_button(name, pressed),
+                    Message::Pause(true) => {
+                        loop {
+                            match recv_paused(&mut stepper.ppu, config.mute_on_pause, cpu_freq) {
+                                Some(Message::Pause(true)) => {}
+                                Some(Message::Shutdown) => return Ok(()),
+                                _ => break,
                             }
-                            
+                        }
+                        // skip over previous iterations
+                        last_tick = Instant::now();
+                    }
+                    Message::Shutdown => return Ok(()),
+                    Message::Rewind(start) => rewinding = start,
+                    // only ever sent to the window
+                    #[cfg(feature = "display")]
+                    Message::RebindKey(..) => {}
+                    Message::Pause(false) => {}
+                    Message::SetSpeed(new_speed) => {
+                        speed = new_speed;
+                        busy_time = Duration::default();
+                        paced_cycles = 0;
+                        last_tick = Instant::now();
+                    }
+                    Message::PixelPointed(posx, posy) => {
+                        // outside of the screen, the light gun doesn't point at any pixel
+                        stepper.pointer =
+                            pointed_pixel(&stepper.ppu.screen, posx, posy).unwrap_or((-1, -1));
+                    }
+                    Message::MouseDoubleClick(posx, posy) => {
+                        if let Some((x, y)) = stepper.ppu.screen.window_to_nes((posx, posy)) {
+                            stepper.ppu.double_click = Some((usize::from(x), usize::from(y)));
                         }
                     }
+                    #[cfg(feature = "screenshot")]
+                    Message::Screenshot(path) => match stepper.ppu.save_frame_png(&path) {
+                        Ok(()) => log::info!("saved screenshot to {}", path.display()),
+                        Err(e) => log::warn!("failed to save screenshot: {e}"),
+                    },
                 }
             }
 
-            if let Err(e) = cpu.tick(&mut ppu) {
+            let frame = stepper.ppu.frame_count();
+            if let Err(e) = stepper.step(cpu) {
                 log::warn!("cpu stopped");
                 return Err(e);
             }
+            if stepper.ppu.frame_count() != frame {
+                for (name, pressed) in pending_buttons.drain(..) {
+                    stepper.ppu.handle_button(name, pressed);
+                }
+                frames += 1;
+                for (idx, &(name, _)) in config.turbo.iter().enumerate() {
+                    if let Some(since) = turbo_held[idx] {
+                        let released = (frames - since) / turbo_frames[idx] % 2 == 1;
+                        stepper.ppu.handle_button(name, !released);
+                    }
+                }
+                on_frame(&stepper.ppu);
+                frames_since_stats += 1;
 
-            for _ in 0..3 {
-                ppu.update(cpu, writer,mouse_x,mouse_y);     
+                if rewinding {
+                    // the oldest snapshot stays, so rewinding stops there
+                    let snapshot = if rewind_buffer.len() > 1 {
+                        rewind_buffer.pop_back()
+                    } else {
+                        rewind_buffer.back().cloned()
+                    };
+                    if let Some((cpu_state, ppu_state)) = snapshot {
+                        cpu.restore(&cpu_state);
+                        // the buttons held in the snapshot may have been released since, and
+                        // that release won't come again, so keep what the player holds now
+                        let buttons = stepper.ppu.buttons;
+                        // made by save_state, so this can't fail
+                        let _ = stepper.ppu.load_state(&ppu_state);
+                        stepper.ppu.buttons = buttons;
+                    }
+                } else if rewind_frames > 0 {
+                    if let Some(cpu_state) = cpu.snapshot() {
+                        if rewind_buffer.len() >= rewind_frames {
+                            rewind_buffer.pop_front();
+                        }
+                        rewind_buffer.push_back((cpu_state, stepper.ppu.save_state()));
+                    }
+                }
+            }
+
+            if stepper.ppu.is_paused() {
+                // paused by the cpu, only a button press resumes
+                while stepper.ppu.is_paused() {
+                    match recv_paused(&mut stepper.ppu, config.mute_on_pause, cpu_freq) {
+                        Some(Message::Button(name, pressed)) => {
+                            stepper.ppu.handle_button(name, pressed);
+                            if pressed {
+                                stepper.ppu.resume();
+                            }
+                        }
+                        Some(Message::Shutdown) => return Ok(()),
+                        Some(_) => {}
+                        // headless, nobody could ever resume
+                        None => stepper.ppu.resume(),
+                    }
+                }
+                last_tick = Instant::now();
             }
         }
 
-        cycles += ITER_PER_CYCLE;
+        cycles += batch_cycles;
+        paced_cycles += batch_cycles;
+
+        if let Some(stats) = &config.stats {
+            if frames_since_stats > 0 {
+                let now = Instant::now();
+                let actual_frame_time = now.duration_since(last_frame_time) / frames_since_stats;
+                // an exponential moving average over about 60 frames
+                average_frame_time += (actual_frame_time.as_secs_f64() - average_frame_time) / 60.0;
+
+                // the receiver may have hung up, that's fine
+                let _ = stats.send(EmulationStats {
+                    target_frame_time,
+                    actual_frame_time,
+                    speed_percent: target_frame_time.as_secs_f64()
+                        / actual_frame_time.as_secs_f64()
+                        * 100.0,
+                    fps: 1.0 / average_frame_time,
+                    behind,
+                });
+
+                frames_since_stats = 0;
+                last_frame_time = now;
+            }
+        }
 
         if let Some(max_cycles) = max_cycles {
             if cycles > max_cycles {
@@ -136,20 +360,49 @@ fn run_ppu<CPU: Cpu>(
             }
         }
 
+        if stop(cpu, &stepper.ppu) {
+            break Ok(());
+        }
+
         let now = Instant::now();
         busy_time += now.duration_since(last_tick);
 
-        let expected_time_spent = Duration::from_secs_f64((1.0 / CPU_FREQ) * cycles as f64);
+        let expected_time_spent =
+            Duration::from_secs_f64(paced_cycles as f64 * cycles_per_tick / (cpu_freq * speed));
+
+        behind = busy_time.saturating_sub(expected_time_spent);
 
-        if expected_time_spent > busy_time {
-            thread::sleep(expected_time_spent - busy_time);
-        } else if cycles % 1000 == 0
-            && (busy_time - expected_time_spent) > Duration::from_secs_f64(0.2)
+        if speed.is_infinite() {
+            behind = Duration::default();
+            // fast forward, don't wait at all
+            busy_time = Duration::default();
+            paced_cycles = 0;
+        } else if expected_time_spent > busy_time {
+            let requested = expected_time_spent - busy_time;
+            match config.pacing {
+                Pacing::Sleep => {
+                    thread::sleep(requested);
+
+                    if now.elapsed() > requested + Duration::from_millis(1) {
+                        oversleeps += 1;
+                    } else {
+                        oversleeps = 0;
+                    }
+                    if oversleeps >= 100 && !warned_oversleep {
+                        log::warn!("sleeping on this platform is imprecise, which can make the emulation stutter. Consider using `Pacing::SpinHybrid` in the `RunConfig`");
+                        warned_oversleep = true;
+                    }
+                }
+                Pacing::SpinHybrid => sleep_spin(requested),
+            }
+        } else if (busy_time - expected_time_spent) > config.behind_threshold
+            && !matches!(last_behind_warning, Some(last) if now.duration_since(last) < Duration::from_secs(1))
         {
-            println!(
+            log::warn!(
                 "emulation behind by {:?}. trying to catch up...",
                 busy_time - expected_time_spent
             );
+            last_behind_warning = Some(now);
         }
 
         last_tick = now;
@@ -165,20 +418,340 @@ pub fn run_cpu_headless_for<CPU>(
 where
     CPU: Cpu + 'static,
 {
-    let (_, mut writer) = Screen::dummy();
+    run_ppu(
+        cpu,
+        Ppu::new(mirroring),
+        &headless_config(),
+        Some(cycle_limit),
+        |_| {},
+        |_, _| false,
+    )
+}
+
+/// Like [`run_cpu_headless_for`], but the buttons in `initial` are pressed from the very first
+/// cycle and held for the whole run. For example, to hold start while a game boots.
+pub fn run_cpu_headless_with_buttons<CPU>(
+    cpu: &mut CPU,
+    mirroring: Mirroring,
+    initial: Buttons,
+    cycle_limit: usize,
+) -> Result<(), CPU::TickError>
+where
+    CPU: Cpu + 'static,
+{
+    let mut ppu = Ppu::new(mirroring);
+    // without a window, nothing ever changes the buttons again
+    ppu.buttons = initial;
+
+    run_ppu(
+        cpu,
+        ppu,
+        &headless_config(),
+        Some(cycle_limit),
+        |_| {},
+        |_, _| false,
+    )
+}
+
+/// Like [`run_cpu_headless_for`], but presses and releases buttons while running, as if a
+/// player did. Every input is `(cycle, button, pressed)`: right before that cpu cycle the
+/// button is pressed (`true`) or released (`false`). Inputs at the same cycle press several
+/// buttons at once. They don't need to be sorted.
+///
+/// Like [`run_cpu_headless`], this runs as fast as possible.
+///
+/// ```
+/// # use tudelft_nes_ppu::{run_cpu_headless_with_input, ButtonName, Cpu, Mirroring};
+/// # fn test(cpu: &mut impl Cpu) {
+/// // hold start and A together for 1000 cycles, somewhere into the title screen
+/// let inputs = vec![
+///     (10_000, ButtonName::Start1, true),
+///     (10_000, ButtonName::A1, true),
+///     (11_000, ButtonName::Start1, false),
+///     (11_000, ButtonName::A1, false),
+/// ];
+/// run_cpu_headless_with_input(cpu, Mirroring::Horizontal, 100_000, inputs);
+/// # }
+/// ```
+pub fn run_cpu_headless_with_input<CPU: Cpu>(
+    cpu: &mut CPU,
+    mirroring: Mirroring,
+    cycle_limit: usize,
+    mut inputs: Vec<(usize, ButtonName, bool)>,
+) -> Result<(), CPU::TickError> {
+    // a stable sort, so inputs at the same cycle keep their order
+    inputs.sort_by_key(|&(cycle, _, _)| cycle);
+    let mut inputs = inputs.into_iter().peekable();
+    let mut stepper = Stepper::new(mirroring);
+
+    for cycle in 0..cycle_limit {
+        while let Some((_, name, pressed)) = inputs.next_if(|&(at, _, _)| at <= cycle) {
+            stepper.ppu.handle_button(name, pressed);
+        }
+        stepper.step(cpu)?;
+    }
+
+    Ok(())
+}
+
+/// Like [`run_cpu_headless_for`], but returns how many frames were drawn in those
+/// `cycle_limit` cycles, for benchmarks or to check how many frames a game needs for
+/// something. Like [`run_cpu_headless_with_input`] this runs as fast as possible, and it runs
+/// exactly `cycle_limit` cycles.
+pub fn run_cpu_headless_stats<CPU: Cpu>(
+    cpu: &mut CPU,
+    mirroring: Mirroring,
+    cycle_limit: usize,
+) -> Result<RunStats, CPU::TickError> {
+    let mut stepper = Stepper::new(mirroring);
+    for _ in 0..cycle_limit {
+        stepper.step(cpu)?;
+    }
+
+    Ok(RunStats {
+        cycles: cycle_limit,
+        frames: stepper.ppu.frame_count() as usize,
+        nmis: stepper.ppu.nmi_count,
+    })
+}
+
+/// Why [`run_cpu_headless_replay`] stopped before the end of the recording
+#[derive(Debug)]
+pub enum ReplayError<E> {
+    /// The recording couldn't be read
+    Io(io::Error),
+    /// This line (counting from 1) of the recording is not a button state
+    InvalidLine(usize),
+    /// The cpu returned an error
+    Cpu(E),
+}
+
+impl<E: Display> Display for ReplayError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read the recording: {e}"),
+            Self::InvalidLine(line) => write!(f, "line {line} of the recording is invalid"),
+            Self::Cpu(e) => write!(f, "cpu error: {e}"),
+        }
+    }
+}
+
+impl<E: Error> Error for ReplayError<E> {}
+
+/// Replays the buttons recorded with [`run_cpu_recording`] without a window, one line of the
+/// recording every frame, and returns after the last one. When the cpu only depends on the
+/// PPU and its input, it does exactly what it did while recording, so a bug that happened
+/// can be reproduced. Like [`run_cpu_headless_with_input`], this runs as fast as possible.
+///
+/// A recording has one line per frame, starting with the frame before the first vblank. Every
+/// line is the state of the buttons during that frame as 4 hex digits, see
+/// [`Buttons::to_bits`].
+pub fn run_cpu_headless_replay<CPU: Cpu>(
+    cpu: &mut CPU,
+    mirroring: Mirroring,
+    input: PathBuf,
+) -> Result<(), ReplayError<CPU::TickError>> {
+    let recording = std::fs::read_to_string(input).map_err(ReplayError::Io)?;
+    let frames = recording
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| {
+            u16::from_str_radix(line.trim(), 16).map_err(|_| ReplayError::InvalidLine(idx + 1))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stepper = Stepper::new(mirroring);
+    let mut held = 0;
+    for bits in frames {
+        // press and release like a player would, so button events work the same
+        for idx in (0..16).filter(|idx| (held ^ bits) & 1 << idx != 0) {
+            if let Some(name) = ButtonName::from_index(idx) {
+                stepper.ppu.handle_button(name, bits & 1 << idx != 0);
+            }
+        }
+        held = bits;
+
+        let frame = stepper.ppu.frame_count();
+        while stepper.ppu.frame_count() == frame {
+            stepper.step(cpu).map_err(ReplayError::Cpu)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The outcome of a test rom, see [`run_test_rom`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TestResult {
+    /// The rom reported that all its tests passed
+    Pass,
+    /// The rom reported a failure, with this error code
+    Fail(u8),
+    /// The rom didn't report a result within the cycle limit
+    Timeout,
+}
+
+impl TestResult {
+    fn from_status(status: Option<u8>) -> Option<Self> {
+        match status? {
+            0x00 => Some(Self::Pass),
+            0x80 | 0x81 => None,
+            code => Some(Self::Fail(code)),
+        }
+    }
+}
+
+/// Runs a test rom headless until it reports a result, or until `max_cycles` cycles have passed.
+///
+/// The result is read through [`Cpu::test_status`], which follows the convention most NES
+/// test roms use to report their result in memory at `0x6000`. Your cpu needs to implement
+/// that method for this function to ever return something other than [`TestResult::Timeout`].
+pub fn run_test_rom<CPU>(
+    cpu: &mut CPU,
+    mirroring: Mirroring,
+    max_cycles: usize,
+) -> Result<TestResult, CPU::TickError>
+where
+    CPU: Cpu + 'static,
+{
+    run_ppu(
+        cpu,
+        Ppu::new(mirroring),
+        &headless_config(),
+        Some(max_cycles),
+        |_| {},
+        |cpu, _| TestResult::from_status(cpu.test_status()).is_some(),
+    )?;
+
+    Ok(TestResult::from_status(cpu.test_status()).unwrap_or(TestResult::Timeout))
+}
+
+/// Runs two NES instances side by side, as if connected with a link cable. Both cpus get their
+/// own PPU and are ticked in lockstep, for `cycle_limit` cycles. Like [`run_cpu_headless`],
+/// no window is opened and this runs as fast as possible.
+///
+/// The cpus can exchange bytes with [`Ppu::link_send`] and [`Ppu::link_recv`]. Bytes sent
+/// during a frame arrive at the other side at the start of the next vblank.
+pub fn run_two_cpus<CPU>(
+    cpu1: &mut CPU,
+    cpu2: &mut CPU,
+    mirroring: Mirroring,
+    cycle_limit: usize,
+) -> Result<(), CPU::TickError>
+where
+    CPU: Cpu + 'static,
+{
+    let mut nes1 = Stepper::new(mirroring);
+    let mut nes2 = Stepper::new(mirroring);
+    let mut frames = nes1.ppu.frame_count();
+
+    for _ in 0..cycle_limit {
+        nes1.step(cpu1)?;
+        nes2.step(cpu2)?;
+
+        // both ppus are in lockstep, so they start a new frame at the same time
+        if nes1.ppu.frame_count() != frames {
+            frames = nes1.ppu.frame_count();
+
+            nes2.ppu.link_in.extend(nes1.ppu.link_out.drain(..));
+            nes1.ppu.link_in.extend(nes2.ppu.link_out.drain(..));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks whether a cpu is deterministic: runs two fresh cpus made by `make_cpu` headless for
+/// `cycles` cycles each, and compares the frames they produced. Returns `false` when they
+/// differ, which means the cpu depends on something other than its rom, like uninitialized
+/// memory, randomness or the time. Like [`run_two_cpus`], this runs as fast as possible.
+pub fn check_determinism<CPU>(
+    make_cpu: impl Fn() -> CPU,
+    mirroring: Mirroring,
+    cycles: usize,
+) -> Result<bool, CPU::TickError>
+where
+    CPU: Cpu + 'static,
+{
+    let run = || {
+        let mut cpu = make_cpu();
+        let mut stepper = Stepper::new(mirroring);
+        for _ in 0..cycles {
+            stepper.step(&mut cpu)?;
+        }
+        Ok((stepper.ppu.frame_count(), stepper.ppu.frame().to_vec()))
+    };
 
-    run_ppu(mirroring, cpu, &mut writer, Some(cycle_limit))
+    let first = run()?;
+    let second = run()?;
+    Ok(first == second)
+}
+
+/// The settings of the headless run functions: the same as [`run_cpu`] uses, except that
+/// they don't wait for real time, since nobody's watching.
+fn headless_config() -> RunConfig {
+    RunConfig {
+        speed: f64::INFINITY,
+        ..RunConfig::default()
+    }
 }
 
 /// Runs the cpu as if connected to a PPU, but doesn't actually open
 /// a window. This can be useful in tests.
+///
+/// Since there is nothing to watch, this runs as fast as possible instead of at the speed of
+/// a real NES, and so do the other headless functions.
 pub fn run_cpu_headless<CPU>(cpu: &mut CPU, mirroring: Mirroring) -> Result<(), CPU::TickError>
 where
     CPU: Cpu + 'static,
 {
-    let (_, mut writer) = Screen::dummy();
+    run_ppu(
+        cpu,
+        Ppu::new(mirroring),
+        &headless_config(),
+        None,
+        |_| {},
+        |_, _| false,
+    )
+}
+
+/// Runs the cpu with the ppu, showing the frames on a custom [`Backend`] instead of in a window.
+/// Runs at the speed of a real NES. Like [`run_cpu_headless`], this doesn't have to be called
+/// from the main thread, and only returns when the cpu returns an error.
+pub fn run_cpu_with_backend<CPU>(
+    cpu: &mut CPU,
+    mirroring: Mirroring,
+    backend: impl Backend + 'static,
+) -> Result<(), CPU::TickError>
+where
+    CPU: Cpu + 'static,
+{
+    let mut ppu = Ppu::with_resolution(mirroring, backend.resolution());
+    ppu.screen = ScreenWriter::custom(Box::new(backend));
+
+    run_ppu(cpu, ppu, &RunConfig::default(), None, |_| {}, |_, _| false)
+}
 
-    run_ppu(mirroring, cpu, &mut writer, None)
+/// A new file name for a screenshot in the current directory, like `screenshot-1700000000123.png`
+#[cfg(all(feature = "display", feature = "screenshot"))]
+fn screenshot_path() -> std::path::PathBuf {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    format!("screenshot-{millis}.png").into()
+}
+
+/// The message a panic was started with, if it has one.
+#[cfg(feature = "display")]
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "<no message>"
+    }
 }
 
 /// Runs the cpu with the ppu. Takes ownership of the cpu, creates
@@ -187,36 +760,264 @@ where
 /// This function *has to be called from the main thread*. This means it will not
 /// work from unit tests. Use [`run_cpu_headless`] there.
 ///
+/// Besides the keys of the [`KeyMap`], F11 toggles fullscreen, holding tab fast-forwards and
+/// holding backspace rewinds (see [`RunConfig::rewind_seconds`]).
+/// With the `screenshot` feature, F12 saves a screenshot to the current directory. With the
+/// `audio` feature, the sound of the [`Apu`](crate::Apu) is played. It stops while the window
+/// doesn't have focus, unless [`RunConfig::mute_on_pause`] is turned off.
+///
 /// # Panics
 /// [`run_cpu`] can panic when the `cpu` returns an Error
-pub fn run_cpu<CPU>(mut cpu: CPU, mirroring: Mirroring)
+#[cfg(feature = "display")]
+pub fn run_cpu<CPU>(cpu: CPU, mirroring: Mirroring)
+where
+    CPU: Cpu + Send + 'static,
+{
+    run_cpu_with_config(cpu, mirroring, RunConfig::default());
+}
+
+/// Like [`run_cpu`], but with custom key bindings. This is a shorthand for setting
+/// [`RunConfig::keymap`] and calling [`run_cpu_with_config`].
+///
+/// # Panics
+/// [`run_cpu_with_keymap`] can panic when the `cpu` returns an Error
+#[cfg(feature = "display")]
+pub fn run_cpu_with_keymap<CPU>(cpu: CPU, mirroring: Mirroring, keymap: KeyMap)
+where
+    CPU: Cpu + Send + 'static,
+{
+    run_cpu_with_config(
+        cpu,
+        mirroring,
+        RunConfig {
+            keymap,
+            ..RunConfig::default()
+        },
+    );
+}
+
+/// Like [`run_cpu`], but with custom settings. See [`RunConfig`] for what can be configured.
+///
+/// # Panics
+/// [`run_cpu_with_config`] can panic when the `cpu` returns an Error. When the `cpu` panics
+/// itself, that panic is continued on the main thread with the original message.
+#[cfg(feature = "display")]
+pub fn run_cpu_with_config<CPU>(cpu: CPU, mirroring: Mirroring, config: RunConfig)
+where
+    CPU: Cpu + Send + 'static,
+{
+    run_window(cpu, mirroring, config, |_| {});
+}
+
+/// Like [`run_cpu`], but calls `on_frame` once for every frame, right after it was rendered.
+/// This can be used to count frames, or record them through [`Ppu::get_frame`].
+///
+/// `on_frame` runs on the emulation thread, in between cpu cycles. It has to be fast: while
+/// it runs, the emulation is stopped and real time passes, so a slow callback makes the
+/// emulation run behind.
+///
+/// To show the state of the PPU somewhere else, like in a debugger window, send a
+/// [`Ppu::debug_snapshot`] to the other thread:
+///
+/// ```no_run
+/// # use tudelft_nes_ppu::{run_cpu_with_hook, Cpu, Mirroring};
+/// # fn run(cpu: impl Cpu + Send + 'static) {
+/// let (tx, rx) = std::sync::mpsc::sync_channel(1);
+/// // ... show what arrives on `rx` ...
+/// run_cpu_with_hook(cpu, Mirroring::Horizontal, move |ppu| {
+///     // skip the frame when the debugger is still busy with the last one
+///     let _ = tx.try_send(ppu.debug_snapshot());
+/// });
+/// # }
+/// ```
+///
+/// # Panics
+/// [`run_cpu_with_hook`] can panic when the `cpu` returns an Error
+#[cfg(feature = "display")]
+pub fn run_cpu_with_hook<CPU>(
+    cpu: CPU,
+    mirroring: Mirroring,
+    on_frame: impl FnMut(&Ppu) + Send + 'static,
+) where
+    CPU: Cpu + Send + 'static,
+{
+    run_window(cpu, mirroring, RunConfig::default(), on_frame);
+}
+
+/// Like [`run_cpu`], but writes which buttons were held during every frame to the file `out`,
+/// to replay them later with [`run_cpu_headless_replay`]. Button presses and releases only
+/// take effect at the start of a frame here (see [`RunConfig::buttons_per_frame`]), so the
+/// replay gives the cpu exactly the same input. The light gun is not recorded.
+///
+/// # Panics
+/// When `out` can't be created, or like [`run_cpu`]
+#[cfg(feature = "display")]
+pub fn run_cpu_recording<CPU>(cpu: CPU, mirroring: Mirroring, out: PathBuf)
 where
     CPU: Cpu + Send + 'static,
 {
-    env::set_var("WAYLAND_DISPLAY", "wayland-1");
+    let mut file = BufWriter::new(File::create(&out).expect("failed to create the recording"));
+    let mut failed = false;
+    let mut record = move |buttons: Buttons| {
+        // flush every frame, since closing the window exits without dropping the file
+        if let Err(e) = writeln!(file, "{:04x}", buttons.to_bits()).and_then(|_| file.flush()) {
+            if !failed {
+                log::warn!("failed to write the recording: {e}");
+                failed = true;
+            }
+        }
+    };
+
+    // nothing is pressed during the first frame
+    record(Buttons::default());
+
+    let config = RunConfig {
+        buttons_per_frame: true,
+        ..RunConfig::default()
+    };
+    run_window(cpu, mirroring, config, move |ppu| {
+        record(ppu.get_joypad_state())
+    });
+}
+
+/// The buttons `key` presses, see [`RunConfig::players`]
+#[cfg(feature = "display")]
+fn keyboard_buttons(
+    keymap: &KeyMap,
+    players: &Option<[PlayerInput; 2]>,
+    key: winit::event::VirtualKeyCode,
+) -> Vec<ButtonName> {
+    let Some(players) = players else {
+        return keymap.get(key).into_iter().collect();
+    };
+
+    players
+        .iter()
+        .enumerate()
+        .filter_map(|(controller, input)| match input {
+            PlayerInput::Keyboard(keymap) => Some(keymap.get(key)?.on_controller(controller)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Makes `key` press `button` from now on, see [`Ppu::rebind_key`]
+#[cfg(feature = "display")]
+fn rebind_key(
+    keymap: &mut KeyMap,
+    players: &mut Option<[PlayerInput; 2]>,
+    button: ButtonName,
+    key: winit::event::VirtualKeyCode,
+) {
+    let Some(players) = players else {
+        keymap.bind(key, button);
+        return;
+    };
+
+    // the key goes to the first player on the keyboard that has the button, and is taken away
+    // from everyone else
+    let mut bound = false;
+    for (controller, input) in players.iter_mut().enumerate() {
+        if let PlayerInput::Keyboard(keymap) = input {
+            if !bound && button.on_controller(controller) == button {
+                keymap.bind(key, button);
+                bound = true;
+            } else {
+                keymap.unbind(key);
+            }
+        }
+    }
+}
+
+/// Opens the window and runs the emulation next to it, see [`run_cpu_with_config`].
+#[cfg(feature = "display")]
+fn run_window<CPU>(
+    mut cpu: CPU,
+    mirroring: Mirroring,
+    config: RunConfig,
+    on_frame: impl FnMut(&Ppu) + Send + 'static,
+) where
+    CPU: Cpu + Send + 'static,
+{
+    if let Some(display) = &config.wayland_display {
+        env::set_var("WAYLAND_DISPLAY", display);
+    }
 
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .with_title("NES")
-        .build(&event_loop)
-        .expect("failed to create window");
+    let mut builder = WindowBuilder::new()
+        .with_title(&config.title)
+        .with_window_icon(config.icon.clone())
+        .with_resizable(config.resizable);
+    if let Some((width, height)) = config.min_size {
+        builder = builder.with_min_inner_size(PhysicalSize::new(width, height));
+    }
+    let window = builder.build(&event_loop).expect("failed to create window");
 
-    //let window_size = window.inner_size();
+    assert!(config.scale >= 1, "the window scale has to be at least 1");
+    let resolution = config.resolution;
+    // leave a bit of room for the taskbar and window decorations
+    let monitor_scale = window.current_monitor().map_or(u32::MAX, |monitor| {
+        let size = monitor.size();
+        (size.width * 9 / 10 / resolution.width).min(size.height * 9 / 10 / resolution.height)
+    });
+    let scale = config.scale.min(monitor_scale).max(1);
 
-    //println!("Window size: {window_size:?}");
     //modification for duck hunt
     //force canvas to take up full window
-    window.set_inner_size(PhysicalSize::new(WIDTH*2,HEIGHT*2));
+    window.set_inner_size(PhysicalSize::new(
+        resolution.width * scale,
+        resolution.height * scale,
+    ));
     let window_size = window.inner_size();
 
     let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-    let pixels = Pixels::new(WIDTH, HEIGHT, surface_texture).expect("failed to create surface");
+    let present_mode = match config.present_mode {
+        PresentMode::AutoVsync => pixels::wgpu::PresentMode::AutoVsync,
+        PresentMode::AutoNoVsync => pixels::wgpu::PresentMode::AutoNoVsync,
+        PresentMode::Fifo => pixels::wgpu::PresentMode::Fifo,
+        PresentMode::Immediate => pixels::wgpu::PresentMode::Immediate,
+        PresentMode::Mailbox => pixels::wgpu::PresentMode::Mailbox,
+    };
+    let pixels = PixelsBuilder::new(resolution.width, resolution.height, surface_texture)
+        .present_mode(present_mode)
+        .build()
+        .expect("failed to create surface");
+
+    let (mut screen, writer, control_tx, window_rx) =
+        Screen::new(pixels, window, resolution, config.integer_scaling);
 
-    let (mut screen, mut writer, control_tx) = Screen::new(pixels, window);
+    let double_click_interval = config.double_click_interval;
+    let mut keymap = config.keymap.clone();
+    let mut players = config.players.clone();
+    let normal_speed = config.speed;
+    let trigger_button = match config.trigger_button {
+        TriggerButton::Left => MouseButton::Left,
+        TriggerButton::Right => MouseButton::Right,
+        TriggerButton::Middle => MouseButton::Middle,
+    };
+    let mut cursor_position = (0.0, 0.0);
+    let mut last_click: Option<(Instant, (f64, f64))> = None;
+
+    // the stream has to stay on this thread, and keeps playing until the event loop ends
+    #[cfg(feature = "audio")]
+    let audio = crate::audio::Audio::new();
+    #[cfg(feature = "audio")]
+    let audio_sink = audio
+        .as_ref()
+        .map(|audio| (audio.sample_rate(), audio.sink()));
 
     let handle = Arc::new(Mutex::new(Some(thread::spawn(move || {
-        match run_ppu(mirroring, &mut cpu, &mut writer, None) {
-            Ok(_) => unreachable!(),
+        let mut ppu = Ppu::with_resolution(mirroring, resolution);
+        ppu.screen = writer;
+        #[cfg(feature = "audio")]
+        if let Some((sample_rate, sink)) = audio_sink {
+            ppu.apu.set_sample_rate(Some(sample_rate));
+            ppu.apu.sink = Some(sink);
+        }
+
+        match run_ppu(&mut cpu, ppu, &config, None, on_frame, |_, _| false) {
+            // the window was closed
+            Ok(()) => cpu.on_exit(),
             Err(e) => {
                 panic!("cpu implementation returned an error: {e}")
             }
@@ -226,16 +1027,46 @@ where
     let mut last = Instant::now();
     let wait_time = Duration::from_secs_f64(1.0 / 60.0);
 
+    #[cfg(feature = "gamepad")]
+    let mut gamepads = crate::gamepad::Gamepads::new();
+
     event_loop.run(move |event, _, control_flow| {
+        #[cfg(feature = "audio")]
+        let _ = &audio;
+        while let Ok(msg) = window_rx.try_recv() {
+            if let Message::RebindKey(name, key) = msg {
+                rebind_key(&mut keymap, &mut players, name, key);
+            }
+        }
+
         #[allow(clippy::single_match)]
         match event {
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
             } => {
+                // let the cpu clean up before the program exits. if the emulation already
+                // stopped, there is nobody to tell
+                let _ = control_tx.send(Message::Shutdown);
+                if let Some(handle) = handle.lock().unwrap().take() {
+                    let _ = handle.join();
+                }
+
                 *control_flow = ControlFlow::Exit;
                 return;
             }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => {
+                screen.resize(size.width, size.height);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::ScaleFactorChanged { new_inner_size, .. },
+                ..
+            } => {
+                screen.resize(new_inner_size.width, new_inner_size.height);
+            }
             Event::WindowEvent {
                 event: WindowEvent::Focused(f),
                 ..
@@ -249,173 +1080,124 @@ where
                 /* DUCK HUNT ADDITION */
                 
                 // println!("{:?}",position);
+                cursor_position = (position.x, position.y);
                 control_tx
                     .send(Message::PixelPointed(position.x,position.y))
                     .expect("failed to send");
                 /* = = = = = = = = = */
             }
             Event::WindowEvent {
-                event:WindowEvent::MouseInput {state: button_state, button: MouseButton::Left, .. } ,
+                event:WindowEvent::MouseInput {state: button_state, button, .. } ,
                 ..
             } => {
-                control_tx
-                    .send(Message::Button(
-                        ButtonName::MouseLeft,
-                        button_state == ElementState::Pressed,
-                    ))
-                    .expect("failed to send");
+                if button == trigger_button {
+                    control_tx
+                        .send(Message::Button(
+                            ButtonName::MouseLeft,
+                            button_state == ElementState::Pressed,
+                        ))
+                        .expect("failed to send");
+                }
+
+                if button == MouseButton::Left && button_state == ElementState::Pressed {
+                    let now = Instant::now();
+                    let (x, y) = cursor_position;
+
+                    match last_click {
+                        Some((time, (last_x, last_y)))
+                            if now.duration_since(time) <= double_click_interval
+                                && (x - last_x).abs() <= 4.0
+                                && (y - last_y).abs() <= 4.0 =>
+                        {
+                            control_tx
+                                .send(Message::MouseDoubleClick(x, y))
+                                .expect("failed to send");
+                            last_click = None;
+                        }
+                        _ => last_click = Some((now, cursor_position)),
+                    }
+                }
             }
             Event::WindowEvent {
                 event: WindowEvent::KeyboardInput { input, .. },
                 ..
             } => {
-                if let Some(code) = input.virtual_keycode {
-                    match code {
-                        VirtualKeyCode::A => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::Left1,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::W => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::Up1,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::D => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::Right1,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::S => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::Down1,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::Space => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::Start1,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::LShift => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::Select1,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::Z | VirtualKeyCode::F => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::B1,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::X | VirtualKeyCode::G => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::A1,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
+                if input.virtual_keycode == Some(winit::event::VirtualKeyCode::Tab) {
+                    let speed = match input.state {
+                        ElementState::Pressed => f64::INFINITY,
+                        ElementState::Released => normal_speed,
+                    };
+                    control_tx
+                        .send(Message::SetSpeed(speed))
+                        .expect("failed to send");
+                }
 
+                if input.virtual_keycode == Some(winit::event::VirtualKeyCode::Back) {
+                    control_tx
+                        .send(Message::Rewind(input.state == ElementState::Pressed))
+                        .expect("failed to send");
+                }
 
-                        VirtualKeyCode::Left | VirtualKeyCode::J => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::Left2,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::Up | VirtualKeyCode::I => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::Up2,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::Right | VirtualKeyCode::L =>  {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::Right2,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::Down | VirtualKeyCode::K => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::Down2,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::Return => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::Start2,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::RShift => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::Select2,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::Numpad1 | VirtualKeyCode::Semicolon => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::B2,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        VirtualKeyCode::Numpad2 | VirtualKeyCode::Apostrophe => {
-                            control_tx
-                                .send(Message::Button(
-                                    ButtonName::A2,
-                                    input.state == ElementState::Pressed,
-                                ))
-                                .expect("failed to send");
-                        }
-                        _ => {}
+                if input.virtual_keycode == Some(winit::event::VirtualKeyCode::F11)
+                    && input.state == ElementState::Pressed
+                {
+                    screen.toggle_fullscreen();
+                }
+
+                #[cfg(feature = "screenshot")]
+                if input.virtual_keycode == Some(winit::event::VirtualKeyCode::F12)
+                    && input.state == ElementState::Pressed
+                {
+                    control_tx
+                        .send(Message::Screenshot(screenshot_path()))
+                        .expect("failed to send");
+                }
+
+                if let Some(code) = input.virtual_keycode {
+                    let pressed = input.state == ElementState::Pressed;
+                    for name in keyboard_buttons(&keymap, &players, code) {
+                        control_tx
+                            .send(Message::Button(name, pressed))
+                            .expect("failed to send");
                     }
                 }
             }
             _ => {}
         }
 
+        #[cfg(feature = "gamepad")]
+        if let Some(gamepads) = &mut gamepads {
+            gamepads.poll(|gamepad, name, pressed| {
+                let controllers: Vec<usize> = match &players {
+                    None => vec![gamepad],
+                    Some(players) => (0..2)
+                        .filter(|&controller| players[controller] == PlayerInput::Gamepad(gamepad))
+                        .collect(),
+                };
+                for controller in controllers {
+                    control_tx
+                        .send(Message::Button(name.on_controller(controller), pressed))
+                        .expect("failed to send");
+                }
+            });
+        }
+
         *control_flow = ControlFlow::WaitUntil(Instant::now() + wait_time);
 
-        if handle.lock().unwrap().as_ref().unwrap().is_finished() {
-            handle
-                .lock()
-                .unwrap()
-                .take()
-                .expect("cpu emulation exited unexpectedly");
+        let mut handle = handle.lock().unwrap();
+        if matches!(handle.as_ref(), Some(h) if h.is_finished()) {
+            let finished = handle.take().expect("cpu emulation exited unexpectedly");
+
+            // the emulation only stops by panicking. do that again here, so the panic
+            // isn't lost when the window closes
+            if let Err(payload) = finished.join() {
+                panic!(
+                    "the emulation thread panicked: {}",
+                    panic_message(&*payload)
+                );
+            }
+
+            *control_flow = ControlFlow::Exit;
             return;
         }
 
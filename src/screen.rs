@@ -1,9 +1,16 @@
-use crate::ppu::colors::Color;
-use crate::WIDTH;
+use crate::ppu::colors::{Color, ColorExt};
+#[cfg(feature = "display")]
+use crate::stretch::StretchRenderer;
+use crate::{HEIGHT, WIDTH};
+#[cfg(feature = "display")]
 use pixels::Pixels;
+#[cfg(feature = "display")]
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
-use winit::window::Window;
+#[cfg(feature = "display")]
+use winit::dpi::PhysicalSize;
+#[cfg(feature = "display")]
+use winit::window::{Fullscreen, Window};
 
 /// A struct containg all the buttons for one controller and whether they are pressed (`true`) or not (`false`)
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
@@ -31,6 +38,43 @@ pub struct Buttons {
 }
 
 impl Buttons {
+    /// Sets whether the button with the given name is pressed.
+    pub fn set(&mut self, name: ButtonName, pressed: bool) {
+        let button = match name {
+            ButtonName::A1 => &mut self.a1,
+            ButtonName::B1 => &mut self.b1,
+            ButtonName::Up1 => &mut self.up1,
+            ButtonName::Down1 => &mut self.down1,
+            ButtonName::Left1 => &mut self.left1,
+            ButtonName::Right1 => &mut self.right1,
+            ButtonName::Start1 => &mut self.start1,
+            ButtonName::Select1 => &mut self.select1,
+            ButtonName::A2 => &mut self.a2,
+            ButtonName::B2 => &mut self.b2,
+            ButtonName::Up2 => &mut self.up2,
+            ButtonName::Down2 => &mut self.down2,
+            ButtonName::Left2 => &mut self.left2,
+            ButtonName::Right2 => &mut self.right2,
+            ButtonName::Start2 => &mut self.start2,
+            ButtonName::Select2 => &mut self.select2,
+            ButtonName::MouseLeft => &mut self.trigger,
+        };
+        *button = pressed;
+    }
+
+    /// The buttons of both controllers as bits: bit `i` is set when
+    /// [`Buttons::get_by_index`]`(i)` is pressed. The light gun is not included.
+    pub fn to_bits(self) -> u16 {
+        (0..16)
+            .filter(|&idx| self.get_by_index(idx))
+            .fold(0, |bits, idx| bits | 1 << idx)
+    }
+
+    /// Whether any button on either controller, or the light gun trigger, is pressed.
+    pub fn any_pressed(&self) -> bool {
+        (0..16).any(|idx| self.get_by_index(idx)) || self.trigger
+    }
+
     pub fn get_by_index(self, idx: u8) -> bool {
         match idx {
             0 => self.a1,
@@ -54,7 +98,9 @@ impl Buttons {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// The name of one of the buttons in [`Buttons`]. The buttons ending in 1 belong to the
+/// first controller, the ones ending in 2 to the second controller.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum ButtonName {
     A1,
     B1,
@@ -72,74 +118,534 @@ pub enum ButtonName {
     Right2,
     Start2,
     Select2,
+    /// The trigger of the light gun, pulled with the left mouse button
     MouseLeft,
 }
 
-pub enum ScreenReader {
-    Dummy,
-    Real {
-        pixels: Box<Mutex<Pixels>>,
-        window: Window,
-    },
+impl ButtonName {
+    /// The index of this button as used by [`Buttons::get_by_index`], or `None` for the
+    /// light gun trigger which isn't part of a controller.
+    pub fn index(self) -> Option<u8> {
+        Some(match self {
+            ButtonName::A1 => 0,
+            ButtonName::B1 => 1,
+            ButtonName::Select1 => 2,
+            ButtonName::Start1 => 3,
+            ButtonName::Up1 => 4,
+            ButtonName::Down1 => 5,
+            ButtonName::Left1 => 6,
+            ButtonName::Right1 => 7,
+            ButtonName::A2 => 8,
+            ButtonName::B2 => 9,
+            ButtonName::Select2 => 10,
+            ButtonName::Start2 => 11,
+            ButtonName::Up2 => 12,
+            ButtonName::Down2 => 13,
+            ButtonName::Left2 => 14,
+            ButtonName::Right2 => 15,
+            ButtonName::MouseLeft => return None,
+        })
+    }
+
+    /// The same button on controller `controller` (0 or 1): `A1.on_controller(1)` is `A2`.
+    /// The light gun trigger stays the same.
+    pub fn on_controller(self, controller: usize) -> Self {
+        match self.index() {
+            Some(idx) => Self::from_index(idx % 8 + 8 * (controller.min(1) as u8))
+                .expect("index is below 16"),
+            None => self,
+        }
+    }
+
+    /// The button with this index, the opposite of [`ButtonName::index`]. Returns `None`
+    /// for indices above 15.
+    pub fn from_index(idx: u8) -> Option<Self> {
+        Some(match idx {
+            0 => ButtonName::A1,
+            1 => ButtonName::B1,
+            2 => ButtonName::Select1,
+            3 => ButtonName::Start1,
+            4 => ButtonName::Up1,
+            5 => ButtonName::Down1,
+            6 => ButtonName::Left1,
+            7 => ButtonName::Right1,
+            8 => ButtonName::A2,
+            9 => ButtonName::B2,
+            10 => ButtonName::Select2,
+            11 => ButtonName::Start2,
+            12 => ButtonName::Up2,
+            13 => ButtonName::Down2,
+            14 => ButtonName::Left2,
+            15 => ButtonName::Right2,
+            _ => return None,
+        })
+    }
+}
+
+/// The size of the frame buffer in pixels. The PPU always draws its 256x240 picture in the
+/// top left corner, a larger frame leaves room around it that the host can draw in with
+/// [`Ppu::set_pixel`](crate::Ppu::set_pixel), [`Ppu::blend_pixel`](crate::Ppu::blend_pixel)
+/// and [`Ppu::draw_rect_outline`](crate::Ppu::draw_rect_outline), like a status bar or a debug
+/// view. Use it with [`Ppu::with_resolution`](crate::Ppu::with_resolution),
+/// [`RunConfig::resolution`](crate::RunConfig::resolution) or [`Backend::resolution`].
+///
+/// ```
+/// # use tudelft_nes_ppu::{Mirroring, Ppu, Resolution};
+/// let resolution = Resolution { width: 256, height: 256 };
+/// let mut ppu = Ppu::with_resolution(Mirroring::Horizontal, resolution);
+/// ppu.set_pixel(0, 250, (255, 0, 0));
+/// assert_eq!(ppu.get_pixel(0, 250), Some((255, 0, 0)));
+/// assert_eq!(ppu.frame().len(), 256 * 256 * 4);
+/// assert_eq!(Resolution::default(), Resolution::NES);
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Resolution {
+    /// The size of the NES screen, 256x240
+    pub const NES: Self = Self {
+        width: WIDTH,
+        height: HEIGHT,
+    };
+
+    /// The length of a frame buffer of this size, with 4 bytes per pixel
+    pub(crate) fn buffer_len(self) -> usize {
+        self.width as usize * self.height as usize * 4
+    }
+
+    /// Whether the pixel at (`x`, `y`) lies within the frame
+    pub(crate) fn contains(self, x: usize, y: usize) -> bool {
+        x < self.width as usize && y < self.height as usize
+    }
+}
+
+impl Default for Resolution {
+    fn default() -> Self {
+        Self::NES
+    }
+}
+
+/// A place to show the frames the PPU renders, and to get input from, other than the window
+/// [`run_cpu`](crate::run_cpu) opens. Run a cpu with it through
+/// [`run_cpu_with_backend`](crate::run_cpu_with_backend).
+///
+/// ```
+/// # use tudelft_nes_ppu::{Backend, ButtonName};
+/// /// Counts how many frames were rendered.
+/// struct FrameCounter(usize);
+///
+/// impl Backend for FrameCounter {
+///     fn present(&mut self, _frame: &[u8]) {
+///         self.0 += 1;
+///     }
+///
+///     fn poll_input(&mut self) -> Option<(ButtonName, bool)> {
+///         None
+///     }
+/// }
+/// ```
+pub trait Backend: Send {
+    /// Shows a finished frame. The frame is [`Backend::resolution`] pixels of 4 bytes each (red,
+    /// green, blue and alpha), row by row. This is called once every frame, at the start of
+    /// vblank.
+    fn present(&mut self, frame: &[u8]);
+
+    /// The size of the frames given to [`Backend::present`]. The default is the size of the
+    /// NES screen, see [`Resolution`] for what happens with a larger one.
+    fn resolution(&self) -> Resolution {
+        Resolution::NES
+    }
+
+    /// Returns the next button that was pressed (`true`) or released (`false`), if any.
+    /// This is called repeatedly until it returns `None`, very often, so it shouldn't block.
+    fn poll_input(&mut self) -> Option<(ButtonName, bool)>;
 }
 
+#[cfg(feature = "display")]
+pub struct ScreenReader {
+    pixels: Box<Mutex<Pixels>>,
+    window: Window,
+    /// Whether the frame is scaled by whole numbers only, see
+    /// [`RunConfig::integer_scaling`](crate::RunConfig::integer_scaling)
+    integer_scaling: bool,
+    /// Draws the frame when it's stretched over the window instead
+    stretch: Option<StretchRenderer>,
+    /// The size of the window right before it entered fullscreen, which it goes back to when
+    /// leaving fullscreen
+    windowed_size: Mutex<PhysicalSize<u32>>,
+    /// The current size of the inside of the window in physical pixels, kept up to date by
+    /// [`Screen::resize`]
+    size: Mutex<(u32, u32)>,
+    /// The size of the frame drawn in the window
+    resolution: Resolution,
+}
+
+// without a window, only buttons from a custom backend come in
+#[cfg_attr(not(feature = "display"), allow(dead_code))]
 pub enum Message {
     Button(ButtonName, bool),
     Pause(bool),
     PixelPointed(f64,f64),
+    /// Two clicks close together in time and space. The position is in window coordinates,
+    /// just like [`Message::PixelPointed`].
+    MouseDoubleClick(f64, f64),
+    /// Change how fast the emulation runs, see [`RunConfig::speed`](crate::RunConfig::speed)
+    SetSpeed(f64),
+    /// The window was closed, stop the emulation
+    Shutdown,
+    /// Start (`true`) or stop (`false`) going back in time, see
+    /// [`RunConfig::rewind_seconds`](crate::RunConfig::rewind_seconds)
+    Rewind(bool),
+    /// Save the current frame as a PNG image at this path
+    #[cfg(feature = "screenshot")]
+    Screenshot(std::path::PathBuf),
+    /// Sent the other way, to the window: make the key press the button from now on, see
+    /// [`Ppu::rebind_key`](crate::Ppu::rebind_key)
+    #[cfg(feature = "display")]
+    RebindKey(ButtonName, winit::event::VirtualKeyCode),
 }
 
+#[cfg(feature = "display")]
 #[derive(Clone)]
 pub struct Screen(pub Arc<ScreenReader>);
 
+/// Every [`ScreenWriter::draw_pixel`] call a PPU made, in the order they were made, see
+/// [`Ppu::record_draw_calls`](crate::Ppu::record_draw_calls).
+///
+/// Unlike the frame buffer, this also shows pixels that were drawn over later in the frame.
+#[derive(Debug, Clone, Default)]
+pub struct DrawLog(Arc<Mutex<Vec<(usize, usize, Color)>>>);
+
+impl DrawLog {
+    /// Takes the `(x, y, color)` of every pixel drawn since the last call
+    pub fn take(&self) -> Vec<(usize, usize, Color)> {
+        std::mem::take(&mut *self.0.lock().expect("failed to lock"))
+    }
+
+    /// How many pixels were drawn since the last call to [`DrawLog::take`]
+    pub fn len(&self) -> usize {
+        self.0.lock().expect("failed to lock").len()
+    }
+
+    /// Whether no pixels were drawn since the last call to [`DrawLog::take`]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 pub enum ScreenWriter {
-    Dummy,
+    Dummy {
+        pixels: Vec<u8>,
+        resolution: Resolution,
+    },
+    /// Like [`ScreenWriter::Dummy`], but keeps a log of all pixels that are drawn
+    Recording {
+        pixels: Vec<u8>,
+        resolution: Resolution,
+        log: DrawLog,
+    },
+    #[cfg(feature = "display")]
     Real {
         screen: Screen,
         pixels: Vec<u8>,
+        resolution: Resolution,
         control_rx: Receiver<Message>,
+        /// Messages for the window, like [`Message::RebindKey`]
+        window_tx: Sender<Message>,
+    },
+    Custom {
+        backend: Box<dyn Backend>,
+        pixels: Vec<u8>,
+        resolution: Resolution,
     },
 }
 
 impl ScreenWriter {
+    pub fn dummy(resolution: Resolution) -> Self {
+        Self::Dummy {
+            pixels: vec![0; resolution.buffer_len()],
+            resolution,
+        }
+    }
+
+    pub fn recording(resolution: Resolution) -> (Self, DrawLog) {
+        let log = DrawLog::default();
+        let writer = Self::Recording {
+            pixels: vec![0; resolution.buffer_len()],
+            resolution,
+            log: log.clone(),
+        };
+        (writer, log)
+    }
+
+    pub fn custom(backend: Box<dyn Backend>) -> Self {
+        let resolution = backend.resolution();
+        Self::Custom {
+            backend,
+            pixels: vec![0; resolution.buffer_len()],
+            resolution,
+        }
+    }
+
+    /// The size of the frame buffer
+    pub fn resolution(&self) -> Resolution {
+        match self {
+            Self::Dummy { resolution, .. }
+            | Self::Recording { resolution, .. }
+            | Self::Custom { resolution, .. } => *resolution,
+            #[cfg(feature = "display")]
+            Self::Real { resolution, .. } => *resolution,
+        }
+    }
+
+    /// Whether a pixel is on the screen, panicking in debug builds when it isn't
+    fn check_on_screen(&self, x: usize, y: usize) -> bool {
+        let Resolution { width, height } = self.resolution();
+        let on_screen = self.resolution().contains(x, y);
+        debug_assert!(
+            on_screen,
+            "pixel ({x}, {y}) is outside of the {width}x{height} screen"
+        );
+        on_screen
+    }
+
+    /// Where the pixel at (`x`, `y`) starts in the frame buffer
+    fn offset(&self, x: usize, y: usize) -> usize {
+        4 * (y * self.resolution().width as usize + x)
+    }
+
+    fn buffer_mut(&mut self) -> &mut [u8] {
+        match self {
+            Self::Dummy { pixels, .. }
+            | Self::Recording { pixels, .. }
+            | Self::Custom { pixels, .. } => pixels,
+            #[cfg(feature = "display")]
+            Self::Real { pixels, .. } => pixels,
+        }
+    }
+
+    /// Draws a pixel in the frame buffer. Coordinates outside of the screen are a bug in the
+    /// PPU: in debug builds they panic with a message saying so, in release builds the pixel
+    /// is left out.
     pub fn draw_pixel(&mut self, x: usize, y: usize, color: Color) {
-        if let Self::Real { pixels, .. } = self {
-            pixels[4 * (y * WIDTH as usize + x)] = color.0;
-            pixels[4 * (y * WIDTH as usize + x) + 1] = color.1;
-            pixels[4 * (y * WIDTH as usize + x) + 2] = color.2;
-            pixels[4 * (y * WIDTH as usize + x) + 3] = 0xff;
+        if !self.check_on_screen(x, y) {
+            return;
+        }
+        if let Self::Recording { log, .. } = self {
+            log.0.lock().expect("failed to lock").push((x, y, color));
+        }
+
+        let offset = self.offset(x, y);
+        self.buffer_mut()[offset..offset + 4].copy_from_slice(&color.to_rgba());
+    }
+
+    /// Blends `color` over the pixel that is already in the frame buffer. An `alpha` of 0
+    /// leaves the pixel as it was, 255 is the same as [`ScreenWriter::draw_pixel`].
+    ///
+    /// Every channel becomes `(color * alpha + old * (255 - alpha)) / 255`, rounded to the
+    /// nearest integer. This can never go outside of `0..=255`.
+    ///
+    /// Coordinates outside of the screen are handled like [`ScreenWriter::draw_pixel`] does.
+    pub fn blend_pixel(&mut self, x: usize, y: usize, color: Color, alpha: u8) {
+        if !self.check_on_screen(x, y) {
+            return;
+        }
+        let offset = self.offset(x, y);
+        let pixels = self.buffer_mut();
+        let alpha = u16::from(alpha);
+
+        for (old, new) in pixels[offset..offset + 3]
+            .iter_mut()
+            .zip([color.0, color.1, color.2])
+        {
+            let blended = (u16::from(new) * alpha + u16::from(*old) * (255 - alpha) + 127) / 255;
+            *old = blended as u8;
+        }
+        pixels[offset + 3] = 0xff;
+    }
+
+    /// Fills the whole frame buffer with one color. This is not logged as drawn pixels by
+    /// [`ScreenWriter::Recording`].
+    pub fn clear(&mut self, color: Color) {
+        for pixel in self.buffer_mut().chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color.to_rgba());
+        }
+    }
+
+    /// Draws the border of a `w` by `h` rectangle with its top left corner at (`x`, `y`).
+    /// The inside of the rectangle is left alone. Parts outside of the screen are clipped.
+    pub fn draw_rect_outline(&mut self, x: usize, y: usize, w: usize, h: usize, color: Color) {
+        if w == 0 || h == 0 {
+            return;
+        }
+
+        let right = x.saturating_add(w - 1);
+        let bottom = y.saturating_add(h - 1);
+        let resolution = self.resolution();
+        let on_screen = |x: usize, y: usize| resolution.contains(x, y);
+
+        for px in x..=right.min(resolution.width as usize - 1) {
+            for py in [y, bottom] {
+                if on_screen(px, py) {
+                    self.draw_pixel(px, py, color);
+                }
+            }
+        }
+        for py in y..=bottom.min(resolution.height as usize - 1) {
+            for px in [x, right] {
+                if on_screen(px, py) {
+                    self.draw_pixel(px, py, color);
+                }
+            }
+        }
+    }
+
+    /// The RGBA frame buffer that is being drawn to. This is the buffer on the emulation side,
+    /// *before* it is presented: it reflects every `draw_pixel` call right away, and reading it
+    /// never locks the `Pixels` instance the window renders from.
+    pub fn frame(&self) -> &[u8] {
+        match self {
+            Self::Dummy { pixels, .. }
+            | Self::Recording { pixels, .. }
+            | Self::Custom { pixels, .. } => pixels,
+            #[cfg(feature = "display")]
+            Self::Real { pixels, .. } => pixels,
+        }
+    }
+
+    /// Receives the next message from the window or [`Backend`] without blocking. There are
+    /// never any messages in headless mode.
+    pub fn try_recv(&mut self) -> Option<Message> {
+        match self {
+            Self::Dummy { .. } | Self::Recording { .. } => None,
+            #[cfg(feature = "display")]
+            Self::Real { control_rx, .. } => control_rx.try_recv().ok(),
+            Self::Custom { backend, .. } => backend
+                .poll_input()
+                .map(|(name, pressed)| Message::Button(name, pressed)),
+        }
+    }
+
+    /// Blocks until the window (or custom backend) sends a message. Returns `None` right away
+    /// when there is nothing that could ever send one.
+    ///
+    /// # Panics
+    /// When the window was closed.
+    pub fn recv(&mut self) -> Option<Message> {
+        match self {
+            Self::Dummy { .. } | Self::Recording { .. } => None,
+            #[cfg(feature = "display")]
+            Self::Real { control_rx, .. } => Some(control_rx.recv().expect("sender closed")),
+            Self::Custom { .. } => loop {
+                if let Some(msg) = self.try_recv() {
+                    break Some(msg);
+                }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            },
+        }
+    }
+
+    /// The NES pixel shown at a position in the window, or `None` when the position is outside
+    /// of the frame or there is no window.
+    #[cfg_attr(not(feature = "display"), allow(unused_variables))]
+    pub fn window_to_nes(&self, pos: (f64, f64)) -> Option<(u16, u16)> {
+        match self {
+            Self::Dummy { .. } | Self::Recording { .. } | Self::Custom { .. } => None,
+            #[cfg(feature = "display")]
+            Self::Real { screen, .. } => screen.window_to_nes(pos),
+        }
+    }
+
+    /// The size of the inside of the window in physical pixels, or `None` when there is no
+    /// window
+    pub fn window_size(&self) -> Option<(u32, u32)> {
+        match self {
+            Self::Dummy { .. } | Self::Recording { .. } | Self::Custom { .. } => None,
+            #[cfg(feature = "display")]
+            Self::Real { screen, .. } => Some(*screen.0.size.lock().expect("failed to lock")),
+        }
+    }
+
+    /// Sends a message to the window, if there is one
+    #[cfg(feature = "display")]
+    pub fn send_to_window(&self, msg: Message) {
+        if let Self::Real { window_tx, .. } = self {
+            // when the window is gone, there is nothing left to change
+            let _ = window_tx.send(msg);
+        }
+    }
+
+    /// Changes the title of the window, if there is one
+    #[cfg_attr(not(feature = "display"), allow(unused_variables))]
+    pub fn set_title(&self, title: &str) {
+        #[cfg(feature = "display")]
+        if let Self::Real { screen, .. } = self {
+            screen.0.window.set_title(title);
         }
     }
 
-    pub fn render_frame(&mut self) {
-        if let Self::Real { pixels, screen, .. } = self {
-            if let ScreenReader::Real {
-                pixels: reader_pixels,
+    /// Shows the frame buffer in the window, or hands it to the [`Backend`]. Returns whether
+    /// the frame went anywhere: without a window (in headless mode) it is only kept in the frame
+    /// buffer, and this returns `false`.
+    ///
+    /// # Panics
+    /// When the surface of the window is not the size of the frame buffer.
+    pub fn render_frame(&mut self) -> bool {
+        match self {
+            Self::Dummy { .. } | Self::Recording { .. } => false,
+            #[cfg(feature = "display")]
+            Self::Real {
+                pixels,
+                screen,
+                resolution,
                 ..
-            } = &*screen.0
-            {
-                reader_pixels
-                    .lock()
-                    .expect("failed to lock")
-                    .frame_mut()
-                    .clone_from_slice(pixels);
+            } => {
+                let mut surface = screen.0.pixels.lock().expect("failed to lock");
+                let frame = surface.frame_mut();
+                assert_eq!(
+                    frame.len(),
+                    pixels.len(),
+                    "the surface of the window has to be {}x{} pixels of 4 bytes",
+                    resolution.width,
+                    resolution.height,
+                );
+                frame.copy_from_slice(pixels);
+                true
+            }
+            Self::Custom {
+                backend, pixels, ..
+            } => {
+                backend.present(pixels);
+                true
             }
         }
     }
 }
 
+#[cfg(feature = "display")]
 impl Screen {
-    pub fn dummy() -> (Screen, ScreenWriter) {
-        (Screen(Arc::new(ScreenReader::Dummy)), ScreenWriter::Dummy)
-    }
-
-    pub fn new(pixels: Pixels, window: Window) -> (Self, ScreenWriter, Sender<Message>) {
+    pub fn new(
+        pixels: Pixels,
+        window: Window,
+        resolution: Resolution,
+        integer_scaling: bool,
+    ) -> (Self, ScreenWriter, Sender<Message>, Receiver<Message>) {
         let buf = pixels.frame().to_vec();
+        let stretch = (!integer_scaling).then(|| StretchRenderer::new(&pixels));
         let (tx, rx) = channel();
+        let (window_tx, window_rx) = channel();
 
-        let screen = Screen(Arc::new(ScreenReader::Real {
+        let screen = Screen(Arc::new(ScreenReader {
             pixels: Box::new(Mutex::new(pixels)),
+            windowed_size: Mutex::new(window.inner_size()),
+            size: Mutex::new(window.inner_size().into()),
             window,
+            integer_scaling,
+            stretch,
+            resolution,
         }));
 
         (
@@ -147,19 +653,70 @@ impl Screen {
             ScreenWriter::Real {
                 screen,
                 pixels: buf,
+                resolution,
                 control_rx: rx,
+                window_tx,
             },
             tx,
+            window_rx,
         )
     }
 
-    pub fn redraw(&mut self) {
-        if let ScreenReader::Real { pixels, .. } = &*self.0 {
-            pixels
+    /// The pixel of the frame shown at a position in the window, or `None` when the position is
+    /// outside of the frame (for example in the black bars around it).
+    pub fn window_to_nes(&self, pos: (f64, f64)) -> Option<(u16, u16)> {
+        if self.0.integer_scaling {
+            let pixels = self.0.pixels.lock().expect("failed to lock");
+            let (x, y) = pixels
+                .window_pos_to_pixel((pos.0 as f32, pos.1 as f32))
+                .ok()?;
+            Some((x as u16, y as u16))
+        } else {
+            // the frame is stretched over the whole window
+            let size = self.0.window.inner_size();
+            crate::run::window_to_frame(pos, (size.width, size.height), self.0.resolution)
+        }
+    }
+
+    /// Called when the window is resized, or its scale factor changes. The surface is resized
+    /// along with the window, after which the next frame is scaled to the new size.
+    pub fn resize(&self, width: u32, height: u32) {
+        *self.0.size.lock().expect("failed to lock") = (width, height);
+        // a minimized window has no size, and the surface can't be empty
+        if width > 0 && height > 0 {
+            self.0
+                .pixels
                 .lock()
                 .expect("failed to lock")
-                .render()
-                .expect("failed to render using pixels library");
+                .resize_surface(width, height)
+                .expect("failed to resize surface");
+        }
+    }
+
+    /// Switches between borderless fullscreen and a window of the size it started with
+    pub fn toggle_fullscreen(&self) {
+        let window = &self.0.window;
+        if window.fullscreen().is_some() {
+            window.set_fullscreen(None);
+            window.set_inner_size(*self.0.windowed_size.lock().expect("failed to lock"));
+        } else {
+            *self.0.windowed_size.lock().expect("failed to lock") = window.inner_size();
+            window.set_fullscreen(Some(Fullscreen::Borderless(None)));
         }
     }
+
+    pub fn redraw(&mut self) {
+        self.0
+            .pixels
+            .lock()
+            .expect("failed to lock")
+            .render_with(|encoder, target, context| {
+                match &self.0.stretch {
+                    Some(stretch) => stretch.render(encoder, target),
+                    None => context.scaling_renderer.render(encoder, target),
+                }
+                Ok(())
+            })
+            .expect("failed to render using pixels library");
+    }
 }
@@ -0,0 +1,66 @@
+use crate::cpu::Cpu;
+use crate::{Mirroring, Ppu};
+
+/// Runs a cpu and a PPU together one cycle at a time, without a window and without waiting
+/// for real time. This gives tests full control: the state of the PPU can be inspected
+/// between any two cycles. All run functions of this crate use this to advance the emulation.
+///
+/// ```
+/// # use tudelft_nes_ppu::{Mirroring, Stepper};
+/// let stepper = Stepper::new(Mirroring::Horizontal);
+/// assert_eq!(stepper.ppu().frame_count(), 0);
+/// ```
+pub struct Stepper {
+    pub(crate) ppu: Ppu,
+    /// The pixel the light gun points at, or `(-1, -1)` when it doesn't point at the screen
+    pub(crate) pointer: (i32, i32),
+    /// Dots run since the APU was last clocked. The APU runs once every 3 dots, like the cpu
+    /// on a real NES, whatever [`Cpu::DOTS_PER_TICK`] is.
+    apu_dots: usize,
+}
+
+impl Stepper {
+    /// Creates a stepper with a new [`Ppu`].
+    pub fn new(mirroring: Mirroring) -> Self {
+        Self::with_ppu(Ppu::new(mirroring))
+    }
+
+    pub(crate) fn with_ppu(ppu: Ppu) -> Self {
+        Self {
+            ppu,
+            pointer: (-1, -1),
+            apu_dots: 0,
+        }
+    }
+
+    /// Runs a single [`Cpu::tick`], and the [`Cpu::DOTS_PER_TICK`] PPU cycles and the APU
+    /// cycles belonging to it.
+    pub fn step<CPU: Cpu>(&mut self, cpu: &mut CPU) -> Result<(), CPU::TickError> {
+        debug_assert!(CPU::DOTS_PER_TICK > 0, "a tick has to move the PPU along");
+        cpu.tick(&mut self.ppu)?;
+        self.ppu.ticks_this_frame += 1;
+
+        self.apu_dots += CPU::DOTS_PER_TICK;
+        while self.apu_dots >= 3 {
+            self.apu_dots -= 3;
+            self.ppu.apu.tick();
+        }
+
+        let (x, y) = self.pointer;
+        for _ in 0..CPU::DOTS_PER_TICK {
+            self.ppu.update(cpu, x, y);
+        }
+
+        Ok(())
+    }
+
+    /// The PPU, to look at its state between steps
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    /// The PPU, to change its state between steps
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+}